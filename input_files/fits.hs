@@ -0,0 +1,6 @@
+data Shape = Circle Int | Rect Int Int
+    deriving (Show, Eq)
+
+fits :: Shape -> Int -> Bool
+fits (Circle r) k = (r * 2) <= k
+fits (Rect w h) k = (w <= k) && (h <= k) && (w > 0) && (h > 0)
@@ -0,0 +1,8 @@
+data Test = IntC Int | BoolC Bool | BoolD Bool Bool
+
+-- the first matching clause applies, so IntC 0 is accepted and other IntC values need n > 5
+foo :: Test -> Bool
+foo (IntC 0) = True
+foo (IntC n) = n > 5
+foo (BoolD True b) = b
+foo _ = False
@@ -0,0 +1,14 @@
+enum Test {
+    IntC(i32),
+    BoolC(bool),
+    BoolD(bool, bool),
+}
+
+fn foo(t: Test) -> bool {
+    match t {
+        Test::IntC(0) => true,
+        Test::IntC(n) => n > 5,
+        Test::BoolD(true, b) => b,
+        _ => false,
+    }
+}
@@ -0,0 +1,5 @@
+data Range = Range Int Int
+  deriving (Show, Eq)
+
+prop_width :: Range -> Property
+prop_width (Range start end) = (start <= end) ==> ((end - start) >= 0)
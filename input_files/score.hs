@@ -0,0 +1,7 @@
+data Item = Weapon Int Int | Potion Int | Junk
+    deriving (Show, Eq)
+
+score :: Item -> Int
+score (Weapon damage weight) = damage * 3 - weight
+score (Potion strength) = strength * 2 + 1
+score Junk = 0
@@ -0,0 +1,10 @@
+data Range = Span Int Int | Point Int
+
+data RangeError = Empty | Negative
+
+validate :: Range -> Either RangeError ()
+validate (Span a b)
+  | a > b = Left Empty
+  | a < 0 = Left Negative
+  | otherwise = Right ()
+validate (Point p) = if p < 0 then Left Negative else Right ()
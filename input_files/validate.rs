@@ -0,0 +1,30 @@
+enum Range {
+    Span(i32, i32),
+    Point(i32),
+}
+
+enum RangeError {
+    Empty,
+    Negative(i32),
+}
+
+fn validate(r: Range) -> Result<(), RangeError> {
+    match r {
+        Range::Span(a, b) => {
+            if a > b {
+                Err(RangeError::Empty)
+            } else if a < 0 {
+                Err(RangeError::Negative(a))
+            } else {
+                Ok(())
+            }
+        }
+        Range::Point(p) => {
+            if p < 0 {
+                Err(RangeError::Negative(p))
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,8 @@
+data Version = Release Int Int | Beta Int
+    deriving (Show, Eq)
+
+lessThan :: Version -> Version -> Bool
+lessThan (Release a b) (Release c d) = (a < c) || ((a == c) && (b < d))
+lessThan (Beta a) (Release c d) = a <= c
+lessThan (Release a b) (Beta c) = a < c
+lessThan (Beta a) (Beta c) = a < c
@@ -7,6 +7,10 @@ use std::fmt;
 pub struct Adt {
     pub name: String,
     pub constructors: Vec<Cons>,
+    /// the types of the predicate's arguments, just the Adt itself for a predicate on one of its values
+    pub args: Vec<Type>,
+    /// the type the predicate returns, Bool, or Int for a function whose preimages are searched for
+    pub output: Type,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -17,10 +21,106 @@ pub struct Cons {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Func {
+    pub name: String,
     pub con: FuncInput,
     pub opp: Operation,
     pub local_binds: Vec<Func>,
+    /// argument patterns of a local bind, eg `c` and `(Node h l r)` in `f c (Node h l r)`,
+    /// or of a top level clause of a predicate with several arguments.
+    /// top level clauses on a single value of the Adt match it against `con` instead
+    pub args: Vec<Pattern>,
+}
+impl Func {
+    /// the patterns of a top level clause, one for each argument of the predicate
+    #[must_use]
+    pub fn patterns(&self) -> Vec<Pattern> {
+        if self.args.is_empty() {
+            vec![Pattern::Con(self.con.clone())]
+        } else {
+            self.args.clone()
+        }
+    }
+
+    /// whether the clause is a `pre ==> post` property
+    #[must_use]
+    pub fn is_property(&self) -> bool {
+        matches!(self.opp, Operation::Implies(_, _))
+    }
+
+    /// the clause of an Int valued function turned into a predicate on its result,
+    /// that it lies between `low` and `high` inclusive
+    #[must_use]
+    pub fn constrain_result(&self, low: i32, high: i32) -> Func {
+        let result = Box::new(self.opp.clone());
+        let opp = if low == high {
+            Operation::Eq(result, Box::new(Operation::IntLit(low)))
+        } else {
+            Operation::And(
+                Box::new(Operation::Geq(
+                    result.clone(),
+                    Box::new(Operation::IntLit(low)),
+                )),
+                Box::new(Operation::Leq(result, Box::new(Operation::IntLit(high)))),
+            )
+        };
+        Func {
+            opp,
+            ..self.clone()
+        }
+    }
+
+    /// the clause with its body cut down to the precondition of the property, True if it has none
+    #[must_use]
+    pub fn precondition(&self) -> Func {
+        let opp = match &self.opp {
+            Operation::Implies(pre, _) => (**pre).clone(),
+            _ => Operation::BoolLit(true),
+        };
+        Func {
+            opp,
+            ..self.clone()
+        }
+    }
 }
+
+/// the clauses of an Int valued function turned into a predicate on its result, that it lies between
+/// `low` and `high` inclusive. a recursive function keeps its clauses as they were under `name'`,
+/// for the recursive calls to get the Int
+#[must_use]
+pub fn constrain_results(funcs: &[Func], low: i32, high: i32) -> Vec<Func> {
+    let Some(name) = funcs.first().map(|f| f.name.clone()) else {
+        return Vec::new();
+    };
+    let inner = format!("{name}'");
+    let redirect = |f: &Func| Func {
+        opp: f.opp.renamed(&name, &inner),
+        local_binds: f
+            .local_binds
+            .iter()
+            .map(|b| Func {
+                opp: b.opp.renamed(&name, &inner),
+                ..b.clone()
+            })
+            .collect(),
+        ..f.clone()
+    };
+
+    let mut constrained = funcs
+        .iter()
+        .map(|f| redirect(f).constrain_result(low, high))
+        .collect::<Vec<Func>>();
+    let recursive = funcs
+        .iter()
+        .any(|f| f.opp.mentions(&name) || f.local_binds.iter().any(|b| b.opp.mentions(&name)));
+    if recursive {
+        constrained.extend(funcs.iter().map(|f| Func {
+            name: inner.clone(),
+            ..redirect(f)
+        }));
+    }
+    constrained
+}
+
 impl fmt::Display for Func {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} -> {}", self.con, self.opp)
@@ -45,6 +145,8 @@ pub enum Operation {
     Sub(Box<Operation>, Box<Operation>),
     Mul(Box<Operation>, Box<Operation>),
     Apply(Box<Operation>, Box<Operation>),
+    /// QuickCheck's `pre ==> post`, true when the precondition doesn't hold
+    Implies(Box<Operation>, Box<Operation>),
 }
 
 impl fmt::Display for Operation {
@@ -66,10 +168,93 @@ impl fmt::Display for Operation {
             Operation::Or(l, r) => write!(f, "({l} \\/ {r})"),
             Operation::Not(o) => write!(f, "!({o})"),
             Operation::Apply(func, arg) => write!(f, "({func} {arg})"),
+            Operation::Implies(l, r) => write!(f, "({l} -> {r})"),
         }
     }
 }
 impl Operation {
+    /// a result of a predicate returning `Either`, `Maybe` or `Result`, `error` being None for
+    /// success. it is satisfied by success, or when searching for `target`, by failing with it
+    #[must_use]
+    pub fn outcome(error: Option<&str>, target: Option<&str>) -> Operation {
+        Operation::BoolLit(error == target)
+    }
+
+    /// `if cond then yes else no` on Bools, folding away the branches that are literals
+    #[must_use]
+    pub fn branch(cond: Operation, yes: Operation, no: Operation) -> Operation {
+        let not = |c: Operation| Box::new(Operation::Not(Box::new(c)));
+        match (yes, no) {
+            (Operation::BoolLit(y), Operation::BoolLit(n)) if y == n => Operation::BoolLit(y),
+            (Operation::BoolLit(true), Operation::BoolLit(false)) => cond,
+            (Operation::BoolLit(false), Operation::BoolLit(true)) => *not(cond),
+            (Operation::BoolLit(true), no) => Operation::Or(Box::new(cond), Box::new(no)),
+            (Operation::BoolLit(false), no) => Operation::And(not(cond), Box::new(no)),
+            (yes, Operation::BoolLit(true)) => Operation::Or(not(cond), Box::new(yes)),
+            (yes, Operation::BoolLit(false)) => Operation::And(Box::new(cond), Box::new(yes)),
+            (yes, no) => Operation::Or(
+                Box::new(Operation::And(Box::new(cond.clone()), Box::new(yes))),
+                Box::new(Operation::And(not(cond), Box::new(no))),
+            ),
+        }
+    }
+
+    /// the function a call applies and its arguments in order, eg `f` and `[c, l]` for `((f c) l)`.
+    /// None when the head of the application isn't a function name
+    #[must_use]
+    pub fn spine(&self) -> Option<(&str, Vec<&Operation>)> {
+        let mut args = Vec::new();
+        let mut head = self;
+        while let Operation::Apply(f, arg) = head {
+            args.push(&**arg);
+            head = f;
+        }
+        args.reverse();
+        match head {
+            Operation::Var(name) => Some((name, args)),
+            _ => None,
+        }
+    }
+
+    /// the operation with the variable, or function, `from` called `to` instead
+    #[must_use]
+    pub fn renamed(&self, from: &str, to: &str) -> Operation {
+        let r = |o: &Operation| Box::new(o.renamed(from, to));
+        match self {
+            Operation::Var(v) if v == from => Operation::Var(to.to_string()),
+            Operation::BoolLit(_) | Operation::IntLit(_) | Operation::Var(_) => self.clone(),
+            Operation::Not(o) => Operation::Not(r(o)),
+            Operation::And(a, b) => Operation::And(r(a), r(b)),
+            Operation::Or(a, b) => Operation::Or(r(a), r(b)),
+            Operation::Gt(a, b) => Operation::Gt(r(a), r(b)),
+            Operation::Lt(a, b) => Operation::Lt(r(a), r(b)),
+            Operation::Eq(a, b) => Operation::Eq(r(a), r(b)),
+            Operation::Neq(a, b) => Operation::Neq(r(a), r(b)),
+            Operation::Leq(a, b) => Operation::Leq(r(a), r(b)),
+            Operation::Geq(a, b) => Operation::Geq(r(a), r(b)),
+            Operation::Add(a, b) => Operation::Add(r(a), r(b)),
+            Operation::Sub(a, b) => Operation::Sub(r(a), r(b)),
+            Operation::Mul(a, b) => Operation::Mul(r(a), r(b)),
+            Operation::Apply(a, b) => Operation::Apply(r(a), r(b)),
+            Operation::Implies(a, b) => Operation::Implies(r(a), r(b)),
+        }
+    }
+
+    /// whether the variable occurs in the operation
+    #[must_use]
+    pub fn mentions(&self, name: &str) -> bool {
+        match self {
+            Operation::Var(v) => v == name,
+            Operation::BoolLit(_) | Operation::IntLit(_) => false,
+            Operation::Not(o) => o.mentions(name),
+            Operation::Apply(func, arg) => func.mentions(name) || arg.mentions(name),
+            _ => {
+                self.left().is_some_and(|l| l.mentions(name))
+                    || self.right().is_some_and(|r| r.mentions(name))
+            }
+        }
+    }
+
     #[must_use]
     pub fn to_haskell(&self) -> String {
         match self {
@@ -94,6 +279,10 @@ impl Operation {
             Operation::Apply(func, arg) => {
                 format!("({} {})", func.to_haskell(), arg.to_haskell())
             }
+            // validation only needs the Bool, so no QuickCheck
+            Operation::Implies(l, r) => {
+                format!("(not {} || {})", l.to_haskell(), r.to_haskell())
+            }
         }
     }
 
@@ -112,6 +301,7 @@ impl Operation {
                 | Operation::Mul(_, _)
                 | Operation::And(_, _)
                 | Operation::Or(_, _)
+                | Operation::Implies(_, _)
         )
     }
 
@@ -129,6 +319,7 @@ impl Operation {
             Operation::Mul(l, _) => Some(l),
             Operation::And(l, _) => Some(l),
             Operation::Or(l, _) => Some(l),
+            Operation::Implies(l, _) => Some(l),
             _ => None,
         }
     }
@@ -146,6 +337,7 @@ impl Operation {
             Operation::Mul(_, r) => Some(r),
             Operation::And(_, r) => Some(r),
             Operation::Or(_, r) => Some(r),
+            Operation::Implies(_, r) => Some(r),
             _ => None,
         }
     }
@@ -156,15 +348,555 @@ pub struct FuncInput {
     pub prefix: String,
     pub input: Vec<String>,
 }
+impl FuncInput {
+    /// the fields fixed by literal sub-patterns, eg the first field of `(IntC 0)` to 0.
+    /// literals are kept as haskell writes them, eg `(-1)` or `True`, the other fields being variables
+    #[must_use]
+    pub fn literals(&self) -> Vec<(usize, Value)> {
+        self.input
+            .iter()
+            .enumerate()
+            .filter_map(|(j, text)| Some((j, literal(text)?)))
+            .collect()
+    }
+
+    /// whether a value can match both patterns, ie they are on the same constructor and don't fix a
+    /// field to different literals
+    #[must_use]
+    pub fn overlaps(&self, other: &FuncInput) -> bool {
+        let theirs = other.literals();
+        self.prefix == other.prefix
+            && self
+                .literals()
+                .iter()
+                .all(|(j, value)| theirs.iter().all(|(k, v)| j != k || value == v))
+    }
+}
 impl fmt::Display for FuncInput {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "({} {})", self.prefix, self.input.join(" "))
     }
 }
 
+// the value of a literal pattern as haskell writes it, eg `0`, `(-1)` or `True`
+fn literal(text: &str) -> Option<Value> {
+    match text {
+        "True" => Some(Value::Bool(true)),
+        "False" => Some(Value::Bool(false)),
+        _ => text
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .parse()
+            .ok()
+            .map(Value::Int),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// a variable, or a literal Int or Bool kept as haskell writes it, eg `go 0 = True`
+    Var(String),
+    Con(FuncInput),
+}
+impl Pattern {
+    /// the value the pattern only matches, when it is a literal
+    #[must_use]
+    pub fn literal(&self) -> Option<Value> {
+        match self {
+            Pattern::Var(text) => literal(text),
+            Pattern::Con(_) => None,
+        }
+    }
+
+    /// whether a value can match both patterns
+    #[must_use]
+    pub fn overlaps(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (Pattern::Con(con), Pattern::Con(other)) => con.overlaps(other),
+            _ => match (self.literal(), other.literal()) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            },
+        }
+    }
+}
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Var(name) => write!(f, "{name}"),
+            Pattern::Con(con) if con.input.is_empty() => write!(f, "{}", con.prefix),
+            Pattern::Con(con) => write!(f, "{con}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Type {
     Bool,
     Int,
     Custom(String),
 }
+
+/// the search space, the range of Int fields and the depth of recursive values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: i32,
+    pub max: i32,
+    pub depth: usize,
+}
+
+/// what the solvers are asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    /// how many distinct values to find
+    pub count: usize,
+    /// find values the predicate returns False for
+    pub negate: bool,
+    /// fixes the constructor of the value, the first one for a predicate with several, by its index in `Adt::constructors`
+    pub constructor: Option<usize>,
+    /// asks for the smallest or largest value of one of its Int fields
+    pub objective: Option<Objective>,
+    /// randomises the search, reproducibly for a given seed
+    pub seed: Option<u64>,
+    /// only the solutions in the cell these hash constraints pick out
+    pub hashes: Vec<Hash>,
+    /// lays the value out in Essence as a variant of records, rather than a tag and
+    /// a variable for every field of every constructor
+    pub variant: bool,
+}
+
+/// a hash constraint over the decision variables, `(sum of coefficient * variable + offset) % modulus = 0`.
+/// Bool variables count as 0 or 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hash {
+    pub terms: Vec<(String, Type, i64)>,
+    pub offset: i64,
+    pub modulus: i64,
+}
+impl fmt::Display for Hash {
+    // as an Essence constraint
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sum = vec![format!("({})", self.offset)];
+        for (var, t, coefficient) in &self.terms {
+            match t {
+                Type::Bool => sum.push(format!("({coefficient} * toInt({var}))")),
+                _ => sum.push(format!("({coefficient} * {var})")),
+            }
+        }
+        write!(f, "(({}) % {}) = 0", sum.join(" + "), self.modulus)
+    }
+}
+impl Hash {
+    /// whether the solution lies in the cell of the hash
+    /// # Panics
+    /// Panics if a variable of the hash has no value in the assignments.
+    #[must_use]
+    pub fn holds(&self, assignments: &[(String, String)]) -> bool {
+        let sum = self
+            .terms
+            .iter()
+            .map(|(var, _, coefficient)| {
+                let val = assignments
+                    .iter()
+                    .find(|(v, _)| v == var)
+                    .map(|(_, val)| val.as_str())
+                    .unwrap_or_else(|| panic!("No assignment for {var}"));
+                let val = match val {
+                    "true" => 1,
+                    "false" => 0,
+                    _ => val.parse::<i64>().expect("Expected an Int or Bool"),
+                };
+                coefficient * val
+            })
+            .sum::<i64>();
+        (sum + self.offset).rem_euclid(self.modulus) == 0
+    }
+}
+
+/// an Int field of the constructor fixed by the query, to push to one end of its domain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Objective {
+    /// index of the field in the constructor's types
+    pub field: usize,
+    pub maximise: bool,
+}
+
+/// a concrete value of the Adt, rebuilt from a solver assignment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Value {
+    Int(i32),
+    Bool(bool),
+    Con(String, Vec<Value>),
+    /// the arguments of a predicate that takes more than a single value of the Adt
+    Args(Vec<Value>),
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Bool(true) => write!(f, "True"),
+            Value::Bool(false) => write!(f, "False"),
+            Value::Con(prefix, fields) => {
+                write!(f, "{prefix}")?;
+                for field in fields {
+                    write!(f, " {}", field.to_haskell_arg())?;
+                }
+                Ok(())
+            }
+            Value::Args(args) => write!(
+                f,
+                "{}",
+                args.iter()
+                    .map(Value::to_haskell_arg)
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
+        }
+    }
+}
+impl Value {
+    /// the value as it has to be written when passed as an argument, ie wrapped in parens when needed
+    #[must_use]
+    pub fn to_haskell_arg(&self) -> String {
+        match self {
+            Value::Int(i) if *i < 0 => format!("({i})"),
+            Value::Con(_, fields) if !fields.is_empty() => format!("({self})"),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Adt {
+    /// whether the predicate takes a single value of the Adt, rather than several arguments
+    #[must_use]
+    pub fn is_single_value(&self) -> bool {
+        matches!(&self.args[..], [Type::Custom(_)])
+    }
+
+    /// the tag the encodings give the constructor, its position in the data declaration counting
+    /// from 1. the encoders and the decoder all go through `tag` and `tagged`, so a clause's tag
+    /// never depends on where, or whether, the predicate matches on the constructor
+    /// # Panics
+    /// Panics if the Adt has no such constructor.
+    #[must_use]
+    pub fn tag(&self, prefix: &str) -> usize {
+        self.constructors
+            .iter()
+            .position(|c| c.prefix == prefix)
+            .unwrap_or_else(|| panic!("Constructor {prefix} not in Adt {}", self.name))
+            + 1
+    }
+
+    /// the constructor with the tag
+    /// # Panics
+    /// Panics if no constructor has the tag.
+    #[must_use]
+    pub fn tagged(&self, tag: usize) -> &Cons {
+        tag.checked_sub(1)
+            .and_then(|i| self.constructors.get(i))
+            .unwrap_or_else(|| panic!("No constructor of {} has tag {tag}", self.name))
+    }
+
+    /// the patterns a catch-all clause, eg `foo _ = False`, stands for after the `clauses` above
+    /// it, one for each constructor none of them match whatever its fields, with its fields ignored
+    #[must_use]
+    pub fn unmatched(&self, clauses: &[Func]) -> Vec<FuncInput> {
+        self.constructors
+            .iter()
+            .filter(|c| {
+                !clauses
+                    .iter()
+                    .any(|f| f.con.prefix == c.prefix && f.con.literals().is_empty())
+            })
+            .map(|c| FuncInput {
+                prefix: c.prefix.clone(),
+                input: vec!["_".to_string(); c.types.len()],
+            })
+            .collect()
+    }
+
+    /// whether every value matches one of the clauses, each given by its argument patterns, as far
+    /// as it tells without the literals: a clause of variables matches anything, and so do clauses
+    /// on each constructor at an argument, their other patterns being variables
+    #[must_use]
+    pub fn exhaustive(&self, clauses: &[Vec<Pattern>]) -> bool {
+        let irrefutable = |p: &Pattern| matches!(p, Pattern::Var(_)) && p.literal().is_none();
+        let on = |patterns: &[Pattern], i: usize, prefix: &str| {
+            patterns.iter().enumerate().all(|(j, p)| match p {
+                Pattern::Con(con) if j == i => con.prefix == prefix && con.literals().is_empty(),
+                p => j != i && irrefutable(p),
+            })
+        };
+        let arity = clauses.first().map_or(0, Vec::len);
+        clauses
+            .iter()
+            .any(|patterns| patterns.iter().all(irrefutable))
+            || (0..arity).any(|i| {
+                self.constructors
+                    .iter()
+                    .all(|c| clauses.iter().any(|patterns| on(patterns, i, &c.prefix)))
+            })
+    }
+
+    /// the position of the first value of the Adt among the arguments of the predicate, the one
+    /// `Query::constructor` fixes. None when it only takes Ints and Bools
+    #[must_use]
+    pub fn value_arg(&self) -> Option<usize> {
+        self.args.iter().position(|t| matches!(t, Type::Custom(_)))
+    }
+
+    /// the variable of a pooled value at a slot, eg `tag_2` or `Node_1_2`. the values of a predicate
+    /// with several Adt arguments are told apart by the argument holding them, eg `arg_1_tag_2`
+    #[must_use]
+    pub fn slot_variable(&self, arg: usize, name: &str, slot: usize) -> String {
+        let values = self
+            .args
+            .iter()
+            .filter(|t| matches!(t, Type::Custom(_)))
+            .count();
+        if values > 1 {
+            format!("{}_{name}_{slot}", arg_variable(arg))
+        } else {
+            format!("{name}_{slot}")
+        }
+    }
+
+    /// whether the named function gives an Int rather than a Bool, as its clauses tell by an
+    /// arithmetic body, an Int literal or an Int field, or by calling a function that does.
+    /// `scope` holds the local binds it may be one of, `funcs` the top level clauses
+    #[must_use]
+    pub fn returns_int(&self, name: &str, funcs: &[Func], scope: &[Func]) -> bool {
+        self.result_is_int(name, funcs, scope, &mut Vec::new())
+            .unwrap_or(false)
+    }
+
+    // None when no clause tells, eg when each returns an argument of a local bind
+    fn result_is_int(
+        &self,
+        name: &str,
+        funcs: &[Func],
+        scope: &[Func],
+        seen: &mut Vec<String>,
+    ) -> Option<bool> {
+        if seen.iter().any(|s| s == name) {
+            return None;
+        }
+        seen.push(name.to_string());
+
+        let local = scope.iter().any(|f| f.name == name);
+        let clauses = if local { scope } else { funcs };
+        clauses
+            .iter()
+            .filter(|f| f.name == name)
+            .find_map(|clause| {
+                let scope = if local {
+                    scope
+                } else {
+                    &clause.local_binds[..]
+                };
+                let patterns = if local {
+                    clause.args.clone()
+                } else {
+                    clause.patterns()
+                };
+                let field = |var: &str| {
+                    patterns.iter().enumerate().find_map(|(i, p)| match p {
+                        Pattern::Var(v) if v == var && !local => self.args.get(i),
+                        Pattern::Con(con) => {
+                            let j = con.input.iter().position(|v| v == var)?;
+                            let c = self.constructors.iter().find(|c| c.prefix == con.prefix)?;
+                            c.types.get(j)
+                        }
+                        Pattern::Var(_) => None,
+                    })
+                };
+                match &clause.opp {
+                    Operation::IntLit(_)
+                    | Operation::Add(_, _)
+                    | Operation::Sub(_, _)
+                    | Operation::Mul(_, _) => Some(true),
+                    Operation::Var(var) => match field(var) {
+                        Some(Type::Int) => Some(true),
+                        Some(Type::Bool) => Some(false),
+                        Some(Type::Custom(_)) => None,
+                        None => self.result_is_int(var, funcs, scope, seen),
+                    },
+                    op @ Operation::Apply(_, _) => {
+                        let (f, _) = op.spine()?;
+                        self.result_is_int(f, funcs, scope, seen)
+                    }
+                    _ => Some(false),
+                }
+            })
+    }
+
+    /// whether values are laid out over the slot pool rather than a flat set of fields, which is
+    /// the case for recursive Adts and for predicates with several arguments
+    #[must_use]
+    pub fn is_pooled(&self) -> bool {
+        self.is_recursive() || !self.is_single_value()
+    }
+
+    #[must_use]
+    pub fn is_recursive(&self) -> bool {
+        self.recursive_arity() > 0
+    }
+
+    /// the largest number of recursive fields in any constructor, ie the branching factor of a value
+    #[must_use]
+    pub fn recursive_arity(&self) -> usize {
+        self.constructors
+            .iter()
+            .map(Cons::recursive_fields)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// recursive values are laid out over a pool of slots like a heap, slot 1 is the root and
+    /// the children of a slot follow directly after the children of the previous slot
+    #[must_use]
+    pub fn child_slot(&self, slot: usize, child: usize) -> usize {
+        self.recursive_arity() * (slot - 1) + 2 + child
+    }
+
+    /// the Int and Bool decision variables of the encoding, the tags included
+    #[must_use]
+    pub fn variables(&self, depth: usize) -> Vec<(String, Type)> {
+        // the tag and fields of a value, named by the given function
+        let fields = |var: &dyn Fn(&str) -> String| {
+            let mut vars = vec![(var("tag"), Type::Int)];
+            for con in &self.constructors {
+                for (j, t) in con.types.iter().enumerate() {
+                    if !matches!(t, Type::Custom(_)) {
+                        vars.push((var(&format!("{}_{}", con.prefix, j + 1)), t.clone()));
+                    }
+                }
+            }
+            vars
+        };
+        let mut vars = Vec::new();
+        for (i, t) in self.args.iter().enumerate() {
+            match t {
+                Type::Custom(_) if self.is_pooled() => {
+                    for slot in 1..=self.slot_count(depth) {
+                        vars.extend(fields(&|name| self.slot_variable(i, name, slot)));
+                    }
+                }
+                Type::Custom(_) => vars.extend(fields(&ToString::to_string)),
+                _ => vars.push((arg_variable(i), t.clone())),
+            }
+        }
+        vars
+    }
+
+    /// the number of argument lists within the bounds whose first value is built with the constructor,
+    /// or with any constructor for None, ie the size of the search space. saturates rather than overflowing
+    #[must_use]
+    pub fn domain_size(&self, con: Option<usize>, bounds: &Bounds) -> u128 {
+        let width = u128::try_from(i64::from(bounds.max) - i64::from(bounds.min) + 1).unwrap_or(0);
+        let size = |con: &Cons, children: u128| {
+            con.types
+                .iter()
+                .map(|t| match t {
+                    Type::Int => width,
+                    Type::Bool => 2,
+                    Type::Custom(_) => children,
+                })
+                .fold(1, u128::saturating_mul)
+        };
+
+        // the number of values up to each depth, built up from the leaves
+        let mut children = 0;
+        for _ in 1..bounds.depth {
+            children = self
+                .constructors
+                .iter()
+                .map(|c| size(c, children))
+                .fold(0, u128::saturating_add);
+        }
+        let values = self
+            .constructors
+            .iter()
+            .map(|c| size(c, children))
+            .fold(0, u128::saturating_add);
+        self.args
+            .iter()
+            .enumerate()
+            .map(|(i, t)| match (t, con) {
+                (Type::Custom(_), Some(con)) if Some(i) == self.value_arg() => {
+                    size(&self.constructors[con], children)
+                }
+                (Type::Custom(_), _) => values,
+                (Type::Bool, _) => 2,
+                (Type::Int, _) => width,
+            })
+            .fold(1, u128::saturating_mul)
+    }
+
+    /// the number of slots needed to hold every value up to the given depth
+    #[must_use]
+    pub fn slot_count(&self, depth: usize) -> usize {
+        let arity = self.recursive_arity();
+        (0..depth)
+            .map(|level| arity.pow(u32::try_from(level).expect("overflow")))
+            .sum()
+    }
+}
+
+/// the decision variable holding an Int or Bool argument of the predicate, by its position
+#[must_use]
+pub fn arg_variable(i: usize) -> String {
+    format!("arg_{}", i + 1)
+}
+
+impl Cons {
+    #[must_use]
+    pub fn recursive_fields(&self) -> usize {
+        self.types
+            .iter()
+            .filter(|t| matches!(t, Type::Custom(_)))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assign(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(var, val)| ((*var).to_string(), (*val).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn hash_sums_ints_and_bools_modulo() {
+        // (2x + 3b + 1) % 4 = 0
+        let hash = Hash {
+            terms: vec![
+                ("x".to_string(), Type::Int, 2),
+                ("b".to_string(), Type::Bool, 3),
+            ],
+            offset: 1,
+            modulus: 4,
+        };
+        assert!(hash.holds(&assign(&[("x", "0"), ("b", "true")])));
+        assert!(!hash.holds(&assign(&[("x", "3"), ("b", "true")])));
+        assert!(!hash.holds(&assign(&[("x", "0"), ("b", "false")])));
+        // negative sums wrap around into the cells too
+        assert!(hash.holds(&assign(&[("x", "-2"), ("b", "true")])));
+        assert!(!hash.holds(&assign(&[("x", "-2"), ("b", "false")])));
+    }
+
+    #[test]
+    #[should_panic(expected = "No assignment for b")]
+    fn hash_needs_every_variable() {
+        let hash = Hash {
+            terms: vec![("b".to_string(), Type::Bool, 1)],
+            offset: 0,
+            modulus: 2,
+        };
+        let _ = hash.holds(&assign(&[("x", "1")]));
+    }
+}
@@ -1,18 +1,36 @@
-use crate::adt::{Adt, Func};
+use crate::adt::{Adt, Bounds, Func, Query};
 
+/// writes the Essence specification for the query and returns its path
 #[must_use]
-pub fn output(
+pub fn output(adt: &Adt, funcs: &[Func], bounds: &Bounds, query: &Query, verbose: bool) -> String {
+    crate::generate::essence::generate_essence_output(adt, funcs, bounds, query, verbose)
+}
+
+/// builds the model with conjure-oxide and solves it in process, returning the assignments
+/// of up to `query.count` solutions
+#[cfg(feature = "oxide")]
+#[must_use]
+pub fn oxide_output(
     adt: &Adt,
     funcs: &[Func],
-    oxide_out: bool,
+    bounds: &Bounds,
+    query: &Query,
     verbose: bool,
-    min: i32,
-    max: i32,
-) -> String {
-    if oxide_out {
-        // crate::generate::oxide_out::generate_oxide_output(adt, funcs, verbose)
-        unimplemented!("Oxide output generation is not implemented yet.");
-    } else {
-        crate::generate::essence::generate_essence_output(adt, funcs, verbose, min, max)
-    }
+) -> Vec<Vec<(String, String)>> {
+    crate::generate::oxide_out::generate_oxide_output(adt, funcs, bounds, query, verbose)
+}
+
+/// stands in for the in process solver in a build without conjure-oxide
+/// # Panics
+/// Always, as the crate was built without the oxide feature.
+#[cfg(not(feature = "oxide"))]
+#[must_use]
+pub fn oxide_output(
+    _adt: &Adt,
+    _funcs: &[Func],
+    _bounds: &Bounds,
+    _query: &Query,
+    _verbose: bool,
+) -> Vec<Vec<(String, String)>> {
+    panic!("This build has no oxide solver, build it with the oxide feature")
 }
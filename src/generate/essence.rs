@@ -1,10 +1,24 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::Write;
 // This module takes in the parsed haskell AST and outputs an Essence specification as raw text.
-use crate::adt::{Adt, Func, Operation, Type};
+use crate::adt::{Adt, Bounds, Func, FuncInput, Operation, Pattern, Query, Type};
+use crate::generate::essence_expr::{BinOp, Expr};
+use crate::generate::essence_pool::{Decl, pool_model};
+
+// stops recursive local binds from unrolling forever
+const MAX_CALL_DEPTH: usize = 64;
+
+// the variable holding the value in the variant encoding, named like the tag it replaces
+// so its solution decodes the same way
+const VARIANT: &str = "tag";
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TeraFunc {
+    tag: usize,
+    /// the literal sub-patterns of the clause, and of the ones above it it mustn't match, and
+    /// the local binds giving an Int matching a clause
+    matches: Option<String>,
     input: String,
     nons: Vec<String>,
 }
@@ -12,27 +26,83 @@ struct TeraFunc {
 pub fn generate_essence_output(
     adt: &Adt,
     funs: &[Func],
+    bounds: &Bounds,
+    query: &Query,
     verbose: bool,
-    min: i32,
-    max: i32,
 ) -> String {
+    let Bounds { min, max, depth } = *bounds;
     let tera = tera::Tera::new("src/templates/*.tera").unwrap();
     let mut context = tera::Context::new();
 
-    // the adt needs flipped because of stack nonsense
+    // the field is read from the root slot of recursive values
+    if let Some(objective) = query.objective {
+        let con = &adt.constructors[query
+            .constructor
+            .expect("An objective needs the constructor to be fixed")];
+        assert!(
+            con.types.get(objective.field) == Some(&Type::Int),
+            "Objective field {} of {} is not an Int",
+            objective.field + 1,
+            con.prefix
+        );
+        let field = format!("{}_{}", con.prefix, objective.field + 1);
+        let field = match adt.value_arg() {
+            Some(arg) if adt.is_pooled() => adt.slot_variable(arg, &field, 1),
+            _ if query.variant => variant_field(&con.prefix, &field).to_string(),
+            _ => field,
+        };
+        let direction = if objective.maximise {
+            "maximising"
+        } else {
+            "minimising"
+        };
+        context.insert("objective", &format!("{direction} {field}"));
+    }
 
-    context.insert("adt", &adt);
-    context.insert(
-        "funcs",
-        &funs
-            .iter()
-            .map(|f| funtext(adt, f.clone(), verbose, min))
-            .collect::<Vec<TeraFunc>>(),
-    );
-    context.insert("min", &min);
-    context.insert("max", &max);
+    let essence_spec = if adt.is_pooled() {
+        // recursive values don't fit in a fixed set of fields, so lay them out over a pool of slots,
+        // which takes predicates with several arguments too
+        let (decls, mut constraints) = pool_model(adt, funs, depth, min, max, query);
+        constraints.extend(query.hashes.iter().map(ToString::to_string));
+        context.insert("decls", &decls);
+        context.insert("constraints", &constraints);
+        tera.render("essence_pool.tera", &context).unwrap()
+    } else if query.variant {
+        // a single value in one variant, whose records only hold the fields of the active constructor
+        let (decls, constraints) = variant_model(adt, funs, bounds, query);
+        context.insert("decls", &decls);
+        context.insert("constraints", &constraints);
+        tera.render("essence_pool.tera", &context).unwrap()
+    } else {
+        context.insert("adt", &adt);
+        context.insert(
+            "funcs",
+            &funs
+                .iter()
+                .enumerate()
+                .map(|(i, f)| funtext(adt, f.clone(), &funs[..i], verbose, min))
+                .collect::<Vec<TeraFunc>>(),
+        );
+        context.insert("min", &min);
+        context.insert("max", &max);
+        context.insert("negate", &query.negate);
+        context.insert(
+            "hashes",
+            &query
+                .hashes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+        );
+        context.insert(
+            "tag",
+            &query
+                .constructor
+                .map(|c| adt.tag(&adt.constructors[c].prefix)),
+        );
 
-    let essence_spec = tera.render("essence.tera", &context).unwrap();
+        tera.render("essence.tera", &context).unwrap()
+    };
 
     if verbose {
         println!("--- Generated Essence Specification ---");
@@ -48,30 +118,117 @@ pub fn generate_essence_output(
     path.to_string()
 }
 
-fn funtext(adt: &Adt, func: Func, _verbose: bool, min: i32) -> TeraFunc {
-    let prefixes = adt
+// the value as a variant over the constructors, each holding a record of its fields
+fn variant_model(
+    adt: &Adt,
+    funs: &[Func],
+    bounds: &Bounds,
+    query: &Query,
+) -> (Vec<Decl>, Vec<String>) {
+    assert!(
+        query.hashes.is_empty(),
+        "Hash constraints range over the tag and field variables, which the variant encoding doesn't have"
+    );
+    let Bounds { min, max, .. } = *bounds;
+    let records = adt
         .constructors
         .iter()
-        .map(|c| c.prefix.clone())
+        .map(|con| {
+            let fields = con
+                .types
+                .iter()
+                .enumerate()
+                .map(|(j, t)| match t {
+                    Type::Int => format!("{}_{} : int({min}..{max})", con.prefix, j + 1),
+                    Type::Bool => format!("{}_{} : bool", con.prefix, j + 1),
+                    Type::Custom(_) => {
+                        unreachable!("recursive Adts are encoded over the slot pool")
+                    }
+                })
+                .collect::<Vec<String>>();
+            // a constructor without fields has nothing to record, it carries a placeholder
+            if fields.is_empty() {
+                format!("{} : int(0..0)", con.prefix)
+            } else {
+                format!("{} : record {{{}}}", con.prefix, fields.join(", "))
+            }
+        })
         .collect::<Vec<String>>();
+    let decls = vec![Decl {
+        name: VARIANT.to_string(),
+        domain: format!("variant {{{}}}", records.join(", ")),
+    }];
 
-    // check the function's input constructor is in the Adt constructors
-    assert!(
-        prefixes.contains(&func.con.prefix),
-        "Function input constructor {} not in Adt constructors {:?}",
-        func.con.prefix,
-        prefixes
-    );
+    let variant = Expr::Var(VARIANT.to_string());
+    let clauses = funs
+        .iter()
+        .enumerate()
+        .map(|(i, func)| {
+            let env = func
+                .con
+                .input
+                .iter()
+                .enumerate()
+                .map(|(j, name)| {
+                    let field = format!("{}_{}", func.con.prefix, j + 1);
+                    (name.clone(), variant_field(&func.con.prefix, &field))
+                })
+                .collect::<HashMap<String, Expr>>();
+            let mut defined = Vec::new();
+            let body = expr(adt, &func.opp, func, &env, MAX_CALL_DEPTH, &mut defined);
+            let active = Expr::active(variant.clone(), &func.con.prefix);
+            let matches = first_match(func, &funs[..i], |j| {
+                variant_field(&func.con.prefix, &format!("{}_{}", func.con.prefix, j + 1))
+            });
+            Expr::all(
+                [
+                    vec![active, matches],
+                    defined,
+                    vec![if query.negate { Expr::not(body) } else { body }],
+                ]
+                .concat(),
+            )
+        })
+        .collect();
 
-    let str_op = convert_variables(&func.opp, func.clone());
+    let mut constraints = vec![Expr::any(clauses).to_string()];
+    if let Some(con) = query.constructor {
+        constraints.push(Expr::active(variant, &adt.constructors[con].prefix).to_string());
+    }
+    (decls, constraints)
+}
+
+// a field of the constructor, read from the record the variant holds
+fn variant_field(prefix: &str, field: &str) -> Expr {
+    Expr::index(Expr::index(Expr::Var(VARIANT.to_string()), prefix), field)
+}
+
+fn funtext(adt: &Adt, func: Func, above: &[Func], _verbose: bool, min: i32) -> TeraFunc {
+    // panics if the function's input constructor isn't one of the Adt's
+    let tag = adt.tag(&func.con.prefix);
+    let matches = first_match(&func, above, |j| {
+        Expr::Var(format!("{}_{}", func.con.prefix, j + 1))
+    });
+
+    let mut defined = Vec::new();
+    let str_op = expr(
+        adt,
+        &func.opp,
+        &func,
+        &HashMap::new(),
+        MAX_CALL_DEPTH,
+        &mut defined,
+    )
+    .to_string();
+    // the local binds giving an Int must match a clause whatever the polarity
+    let matches = Expr::all([vec![matches], defined].concat());
 
-    let not_prefixes = prefixes
-        .iter()
-        .filter(|p| *p != &func.con.prefix)
-        .collect::<Vec<&String>>();
     let mut nons = Vec::new();
-    for np in &not_prefixes {
-        let con = adt.constructors.iter().find(|c| &c.prefix == *np).unwrap();
+    for con in adt
+        .constructors
+        .iter()
+        .filter(|c| c.prefix != func.con.prefix)
+    {
         for (j, t) in con.types.iter().enumerate() {
             match t {
                 Type::Int => {
@@ -80,212 +237,194 @@ fn funtext(adt: &Adt, func: Func, _verbose: bool, min: i32) -> TeraFunc {
                 Type::Bool => {
                     nons.push(format!(" {}_{} = false", con.prefix, j + 1));
                 }
-                Type::Custom(_) => todo!(),
+                Type::Custom(_) => unreachable!("recursive Adts are encoded over the slot pool"),
             }
         }
     }
 
     TeraFunc {
+        tag,
+        matches: (matches != Expr::Bool(true)).then(|| matches.to_string()),
         input: str_op,
         nons,
     }
 }
 
-fn convert_variables(op: &Operation, func: Func) -> String {
+// the condition for the clause to be the one that applies, as haskell takes the first clause that
+// matches: its literal sub-patterns hold, and those of the clauses above it on the constructor don't.
+// `field` gives the variable of a field of the constructor
+fn first_match(func: &Func, above: &[Func], field: impl Fn(usize) -> Expr) -> Expr {
+    let matches = |con: &FuncInput| {
+        Expr::all(
+            con.literals()
+                .iter()
+                .map(|(j, value)| Expr::bin(BinOp::Eq, field(*j), Expr::value(value)))
+                .collect(),
+        )
+    };
+    let mut conds = vec![matches(&func.con)];
+    conds.extend(
+        above
+            .iter()
+            .filter(|f| f.con.overlaps(&func.con))
+            .map(|f| Expr::not(matches(&f.con))),
+    );
+    Expr::all(conds)
+}
+
+// the Essence expression of an operation in a clause, its variables being the fields of the
+// clause's constructor, or inside a local bind, bound in `env` to the arguments it is applied to.
+// `defined` gathers the conditions for the local binds it calls to match a clause
+fn expr(
+    adt: &Adt,
+    op: &Operation,
+    func: &Func,
+    env: &HashMap<String, Expr>,
+    fuel: usize,
+    defined: &mut Vec<Expr>,
+) -> Expr {
+    let bin = |op: BinOp, l: &Operation, r: &Operation, defined: &mut Vec<Expr>| {
+        Expr::bin(
+            op,
+            expr(adt, l, func, env, fuel, defined),
+            expr(adt, r, func, env, fuel, defined),
+        )
+    };
     match op {
-        Operation::Var(name) => {
+        Operation::BoolLit(b) => Expr::Bool(*b),
+        Operation::IntLit(i) => Expr::Int(*i),
+        Operation::Var(name) => env.get(name).cloned().unwrap_or_else(|| {
             let index = func
                 .con
                 .input
                 .iter()
                 .position(|n| n == name)
                 .expect("Variable name not found in function input");
-            format!("{}_{}", func.con.prefix, index + 1)
-        }
-        Operation::Add(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} + {right}")
-        }
-        Operation::Gt(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} > {right}")
-        }
-        Operation::Lt(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} < {right}")
-        }
-        Operation::Eq(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} = {right}")
-        }
-        Operation::Neq(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} != {right}")
-        }
-        Operation::Leq(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} <= {right}")
-        }
-        Operation::Geq(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} >= {right}")
-        }
-        Operation::Sub(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} - {right}")
-        }
-        Operation::Mul(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} * {right}")
-        }
-        Operation::And(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} && {right}")
-        }
-        Operation::Or(x, y) => {
-            let left = convert_variables(x, func.clone());
-            let right = convert_variables(y, func.clone());
-            format!("{left} || {right}")
-        }
-        Operation::Not(x) => {
-            let val = convert_variables(x, func.clone());
-            format!("not {val}")
-        }
-        Operation::Apply(f, arg) => apply(f, arg, func),
-
-        x => x.to_string(),
+            Expr::Var(format!("{}_{}", func.con.prefix, index + 1))
+        }),
+        Operation::And(l, r) => bin(BinOp::And, l, r, defined),
+        Operation::Or(l, r) => bin(BinOp::Or, l, r, defined),
+        Operation::Implies(l, r) => bin(BinOp::Implies, l, r, defined),
+        Operation::Not(o) => Expr::not(expr(adt, o, func, env, fuel, defined)),
+        Operation::Gt(l, r) => bin(BinOp::Gt, l, r, defined),
+        Operation::Lt(l, r) => bin(BinOp::Lt, l, r, defined),
+        Operation::Eq(l, r) => bin(BinOp::Eq, l, r, defined),
+        Operation::Neq(l, r) => bin(BinOp::Neq, l, r, defined),
+        Operation::Leq(l, r) => bin(BinOp::Leq, l, r, defined),
+        Operation::Geq(l, r) => bin(BinOp::Geq, l, r, defined),
+        Operation::Add(l, r) => bin(BinOp::Add, l, r, defined),
+        Operation::Sub(l, r) => bin(BinOp::Sub, l, r, defined),
+        Operation::Mul(l, r) => bin(BinOp::Mul, l, r, defined),
+        Operation::Apply(_, _) => apply(adt, op, func, env, fuel, defined),
     }
 }
 
-fn apply(f: &Operation, arg: &Operation, func: Func) -> String {
-    // get all functions in local_binds with prefix matching f
-    let funcs = func
+// substitutes the arguments into the bodies of the local binds the application calls, holding if
+// the first clause whose patterns match does, or for a local bind giving an Int, the sum of the
+// bodies weighted by whether their clause is that one, adding to `defined` that one is
+fn apply(
+    adt: &Adt,
+    op: &Operation,
+    func: &Func,
+    env: &HashMap<String, Expr>,
+    fuel: usize,
+    defined: &mut Vec<Expr>,
+) -> Expr {
+    assert!(
+        fuel > 0,
+        "Local binds of {} do not terminate when unrolled",
+        func.name
+    );
+
+    let Some((name, args)) = op.spine() else {
+        panic!("Expected a function name at the head of {op}");
+    };
+    let args = args
+        .into_iter()
+        .map(|arg| expr(adt, arg, func, env, fuel, defined))
+        .collect::<Vec<Expr>>();
+
+    let binds = func
         .local_binds
         .iter()
-        .filter(|fb| match f {
-            Operation::Var(name) => fb.con.prefix == *name,
-            _ => false,
-        })
+        .filter(|fb| fb.con.prefix == name)
         .collect::<Vec<&Func>>();
+    assert!(
+        !binds.is_empty(),
+        "Function {name} not found in local binds"
+    );
 
-    if funcs.is_empty() {
-        panic!(
-            "Function {} not found in local binds",
-            match f {
-                Operation::Var(name) => name.clone(),
-                _ => "unknown".to_string(),
+    let mut options = Vec::new();
+    // the patterns of the clauses above, and the condition for the arguments to match them
+    let mut above: Vec<(&[Pattern], Expr)> = Vec::new();
+    for fb in &binds {
+        assert!(
+            fb.args.len() == args.len(),
+            "Function {name} applied to {} arguments, expected {}",
+            args.len(),
+            fb.args.len()
+        );
+        let mut env = env.clone();
+        let mut conds = Vec::new();
+        for (pattern, arg) in fb.args.iter().zip(&args) {
+            match (pattern, pattern.literal()) {
+                (_, Some(value)) => {
+                    conds.push(Expr::bin(BinOp::Eq, arg.clone(), Expr::value(&value)));
+                }
+                (Pattern::Var(var), None) => {
+                    env.insert(var.clone(), arg.clone());
+                }
+                (Pattern::Con(con), None) => {
+                    panic!("Constructor pattern {con} in a local bind of a non-recursive Adt")
+                }
             }
+        }
+
+        let matched = Expr::all(conds);
+        let mut conds = vec![matched.clone()];
+        conds.extend(
+            above
+                .iter()
+                .filter(|(earlier, _)| earlier.iter().zip(&fb.args).all(|(e, p)| e.overlaps(p)))
+                .map(|(_, m)| Expr::not(m.clone())),
         );
+        let mut inner = Vec::new();
+        let body = expr(adt, &fb.opp, func, &env, fuel - 1, &mut inner);
+        options.push((Expr::all(conds), body, inner));
+        above.push((&fb.args, matched));
     }
 
-    let mut apply_str = String::new();
-
-    for (i, fb) in funcs.iter().enumerate() {
-        let input = fb.con.input.first().expect("Function has no input");
-
-        let arg_str = convert_variables(arg, func.clone());
-
-        let replaced_op = replace_variable(&fb.opp, input, &arg_str, func.clone());
-
-        apply_str.push_str(&format!("({})", replaced_op));
-
-        if i < funcs.len() - 1 {
-            apply_str.push_str(" \\/ ");
-        }
+    let int = adt.returns_int(name, &[], &func.local_binds);
+    let patterns = binds
+        .iter()
+        .map(|fb| fb.args.clone())
+        .collect::<Vec<Vec<Pattern>>>();
+    if int && !adt.exhaustive(&patterns) {
+        defined.push(Expr::any(
+            options
+                .iter()
+                .map(|(cond, _, inner)| Expr::all([vec![cond.clone()], inner.clone()].concat()))
+                .collect(),
+        ));
+    } else {
+        defined.extend(
+            options
+                .iter()
+                .filter(|(_, _, inner)| !inner.is_empty())
+                .map(|(cond, _, inner)| {
+                    Expr::bin(BinOp::Implies, cond.clone(), Expr::all(inner.clone()))
+                }),
+        );
     }
 
-    apply_str
-}
-
-fn replace_variable(op: &Operation, var_name: &String, replacement: &String, func: Func) -> String {
-    match op {
-        Operation::Var(name) => {
-            if name == var_name {
-                replacement.clone()
-            } else {
-                let index = func
-                    .con
-                    .input
-                    .iter()
-                    .position(|n| n == name)
-                    .expect("Variable name not found in function input");
-                format!("{}_{}", func.con.prefix, index + 1)
-            }
-        }
-        Operation::Add(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} + {right}")
-        }
-        Operation::Gt(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} > {right}")
-        }
-        Operation::Lt(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} < {right}")
-        }
-        Operation::Eq(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} = {right}")
-        }
-        Operation::Neq(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} != {right}")
-        }
-        Operation::Leq(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} <= {right}")
-        }
-        Operation::Geq(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} >= {right}")
-        }
-        Operation::Sub(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} - {right}")
-        }
-        Operation::Mul(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} * {right}")
-        }
-        Operation::And(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} && {right}")
-        }
-        Operation::Or(x, y) => {
-            let left = replace_variable(x, var_name, replacement, func.clone());
-            let right = replace_variable(y, var_name, replacement, func.clone());
-            format!("{left} || {right}")
-        }
-        Operation::Not(x) => {
-            let val = replace_variable(x, var_name, replacement, func.clone());
-            format!("not {val}")
-        }
-        Operation::Apply(f, arg) => {
-            let func_str = replace_variable(f, var_name, replacement, func.clone());
-            let arg_str = replace_variable(arg, var_name, replacement, func.clone());
-            format!("{}({})", func_str, arg_str)
-        }
-        x => x.to_string(),
+    let options = options.into_iter().map(|(cond, body, _)| (cond, body));
+    if int {
+        Expr::cases(options.collect())
+    } else {
+        Expr::any(
+            options
+                .map(|(cond, body)| Expr::all(vec![cond, body]))
+                .collect(),
+        )
     }
 }
@@ -0,0 +1,293 @@
+// A small Essence expression AST, printed with only the parentheses the precedences of its operators
+// need. Both encodings build their constraints out of it rather than concatenating strings.
+use std::fmt;
+
+use crate::adt::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Implies,
+    Or,
+    And,
+    Eq,
+    Neq,
+    Lt,
+    Leq,
+    Gt,
+    Geq,
+    Add,
+    Sub,
+    Mul,
+}
+
+// how a chain of operators of the same precedence groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assoc {
+    /// regrouping doesn't change the value, eg `a /\ b /\ c`
+    Both,
+    Left,
+    /// a chain needs parentheses, eg `(a = b) = c`
+    None,
+}
+
+// the binding strength of unary `!`, tighter than any binary operator
+const PREFIX: u16 = 2000;
+
+impl BinOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Implies => "->",
+            BinOp::Or => "\\/",
+            BinOp::And => "/\\",
+            BinOp::Eq => "=",
+            BinOp::Neq => "!=",
+            BinOp::Lt => "<",
+            BinOp::Leq => "<=",
+            BinOp::Gt => ">",
+            BinOp::Geq => ">=",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+        }
+    }
+
+    // as in conjure's operator table
+    fn fixity(self) -> (u16, Assoc) {
+        match self {
+            BinOp::Implies => (50, Assoc::None),
+            BinOp::Or => (110, Assoc::Both),
+            BinOp::And => (120, Assoc::Both),
+            BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Leq | BinOp::Gt | BinOp::Geq => {
+                (400, Assoc::None)
+            }
+            BinOp::Add => (600, Assoc::Both),
+            BinOp::Sub => (600, Assoc::Left),
+            BinOp::Mul => (700, Assoc::Both),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Bool(bool),
+    Int(i32),
+    Var(String),
+    /// the field of a record, or the value of a variant, with the given label
+    Index(Box<Expr>, String),
+    /// whether the variant holds the given label
+    Active(Box<Expr>, String),
+    Not(Box<Expr>),
+    /// 1 when the Bool holds, 0 otherwise
+    ToInt(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    #[must_use]
+    pub fn bin(op: BinOp, left: Expr, right: Expr) -> Expr {
+        Expr::Bin(op, Box::new(left), Box::new(right))
+    }
+
+    #[must_use]
+    pub fn index(e: Expr, label: &str) -> Expr {
+        Expr::Index(Box::new(e), label.to_string())
+    }
+
+    #[must_use]
+    pub fn active(e: Expr, label: &str) -> Expr {
+        Expr::Active(Box::new(e), label.to_string())
+    }
+
+    /// the negation, of a literal being the other literal
+    #[must_use]
+    pub fn not(e: Expr) -> Expr {
+        match e {
+            Expr::Bool(b) => Expr::Bool(!b),
+            e => Expr::Not(Box::new(e)),
+        }
+    }
+
+    /// an Int or Bool value as a literal
+    #[must_use]
+    pub fn value(value: &Value) -> Expr {
+        match value {
+            Value::Int(i) => Expr::Int(*i),
+            Value::Bool(b) => Expr::Bool(*b),
+            _ => panic!("{value} is not an Int or Bool literal"),
+        }
+    }
+
+    /// the conjunction of the expressions, leaving out the ones that are just true
+    #[must_use]
+    pub fn all(exprs: Vec<Expr>) -> Expr {
+        exprs
+            .into_iter()
+            .filter(|e| *e != Expr::Bool(true))
+            .reduce(|l, r| Expr::bin(BinOp::And, l, r))
+            .unwrap_or(Expr::Bool(true))
+    }
+
+    /// the disjunction of the expressions, leaving out the ones that are just false
+    #[must_use]
+    pub fn any(exprs: Vec<Expr>) -> Expr {
+        exprs
+            .into_iter()
+            .filter(|e| *e != Expr::Bool(false))
+            .reduce(|l, r| Expr::bin(BinOp::Or, l, r))
+            .unwrap_or(Expr::Bool(false))
+    }
+
+    /// the value of the case whose condition holds, as the sum of the values weighted by their
+    /// conditions, so the conditions must exclude each other
+    #[must_use]
+    pub fn cases(cases: Vec<(Expr, Expr)>) -> Expr {
+        cases
+            .into_iter()
+            .filter(|(cond, _)| *cond != Expr::Bool(false))
+            .map(|(cond, value)| match cond {
+                Expr::Bool(true) => value,
+                cond => Expr::bin(BinOp::Mul, Expr::ToInt(Box::new(cond)), value),
+            })
+            .reduce(|l, r| Expr::bin(BinOp::Add, l, r))
+            .unwrap_or(Expr::Int(0))
+    }
+
+    fn precedence(&self) -> u16 {
+        match self {
+            Expr::Bin(op, _, _) => op.fixity().0,
+            Expr::Not(_) => PREFIX,
+            _ => u16::MAX,
+        }
+    }
+
+    // whether the expression needs parentheses as an operand of `op`, on its right if `right`
+    fn needs_parens(&self, op: BinOp, right: bool) -> bool {
+        let (precedence, assoc) = op.fixity();
+        match self {
+            // keeps `x - -1` from reading as a decrement
+            Expr::Int(i) => *i < 0,
+            Expr::Bin(inner, _, _) if self.precedence() == precedence => match assoc {
+                Assoc::Both => right && *inner != op,
+                Assoc::Left => right,
+                Assoc::None => true,
+            },
+            _ => self.precedence() < precedence,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Bool(b) => write!(f, "{b}"),
+            Expr::Int(i) => write!(f, "{i}"),
+            Expr::Var(name) => write!(f, "{name}"),
+            Expr::Index(e, label) => write!(f, "{e}[{label}]"),
+            Expr::Active(e, label) => write!(f, "active({e}, {label})"),
+            Expr::ToInt(e) => write!(f, "toInt({e})"),
+            Expr::Not(e) => match **e {
+                Expr::Bool(_) | Expr::Var(_) | Expr::Index(_, _) | Expr::Active(_, _) => {
+                    write!(f, "!{e}")
+                }
+                _ => write!(f, "!({e})"),
+            },
+            Expr::Bin(op, l, r) => {
+                let operand = |e: &Expr, right: bool| {
+                    if e.needs_parens(*op, right) {
+                        format!("({e})")
+                    } else {
+                        e.to_string()
+                    }
+                };
+                write!(
+                    f,
+                    "{} {} {}",
+                    operand(l, false),
+                    op.symbol(),
+                    operand(r, true)
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> Expr {
+        Expr::Var(name.to_string())
+    }
+
+    #[test]
+    fn implication_chains_keep_their_parentheses() {
+        let left = Expr::bin(
+            BinOp::Implies,
+            Expr::bin(BinOp::Implies, var("a"), var("b")),
+            var("c"),
+        );
+        let right = Expr::bin(
+            BinOp::Implies,
+            var("a"),
+            Expr::bin(BinOp::Implies, var("b"), var("c")),
+        );
+        assert_eq!(left.to_string(), "(a -> b) -> c");
+        assert_eq!(right.to_string(), "a -> (b -> c)");
+    }
+
+    #[test]
+    fn negation_wraps_compound_operands() {
+        let and = Expr::bin(BinOp::And, var("a"), var("b"));
+        assert_eq!(Expr::not(and).to_string(), "!(a /\\ b)");
+        assert_eq!(Expr::not(var("a")).to_string(), "!a");
+        assert_eq!(Expr::not(Expr::Bool(true)), Expr::Bool(false));
+    }
+
+    #[test]
+    fn subtraction_groups_to_the_left() {
+        let right = Expr::bin(
+            BinOp::Sub,
+            var("a"),
+            Expr::bin(BinOp::Sub, var("b"), var("c")),
+        );
+        let left = Expr::bin(
+            BinOp::Sub,
+            Expr::bin(BinOp::Sub, var("a"), var("b")),
+            var("c"),
+        );
+        assert_eq!(right.to_string(), "a - (b - c)");
+        assert_eq!(left.to_string(), "a - b - c");
+        assert_eq!(
+            Expr::bin(BinOp::Sub, var("a"), Expr::Int(-1)).to_string(),
+            "a - (-1)"
+        );
+    }
+
+    #[test]
+    fn nested_comparisons_are_parenthesised() {
+        let lt = Expr::bin(BinOp::Lt, var("a"), var("b"));
+        assert_eq!(
+            Expr::bin(BinOp::Eq, lt.clone(), var("c")).to_string(),
+            "(a < b) = c"
+        );
+        assert_eq!(
+            Expr::bin(BinOp::Eq, var("c"), lt).to_string(),
+            "c = (a < b)"
+        );
+        let sum = Expr::bin(BinOp::Add, var("a"), var("b"));
+        assert_eq!(
+            Expr::bin(BinOp::Leq, sum, var("c")).to_string(),
+            "a + b <= c"
+        );
+    }
+
+    #[test]
+    fn all_and_any_drop_the_neutral_literals() {
+        assert_eq!(
+            Expr::all(vec![Expr::Bool(true), var("a"), var("b")]).to_string(),
+            "a /\\ b"
+        );
+        assert_eq!(Expr::all(Vec::new()), Expr::Bool(true));
+        assert_eq!(Expr::any(vec![Expr::Bool(false)]), Expr::Bool(false));
+    }
+}
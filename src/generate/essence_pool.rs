@@ -0,0 +1,540 @@
+// Essence encoding for recursive Adts (eg trees). A value is laid out over a fixed pool of slots,
+// slot 1 being the root, and the predicate is unrolled over the slots up to the depth bound.
+// Int and Bool arguments of the predicate next to the value get a variable each.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::adt::{Adt, Func, FuncInput, Operation, Pattern, Query, Type, arg_variable};
+use crate::generate::essence_expr::{BinOp, Expr};
+
+// stops predicates that never recurse on a smaller value from unrolling forever
+const MAX_CALL_DEPTH: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Decl {
+    pub name: String,
+    pub domain: String,
+}
+
+/// a value bound to a variable while unrolling the predicate
+#[derive(Debug, Clone)]
+enum Bound {
+    /// an Int or Bool expression over the decision variables
+    Expr(Expr),
+    /// the value rooted at a slot of the pool of an argument
+    Slot(usize, usize),
+}
+
+struct Pool<'a> {
+    adt: &'a Adt,
+    funcs: &'a [Func],
+    depth: usize,
+    min: i32,
+}
+
+/// returns the `find` declarations and the constraints of the pool encoding for the query
+pub fn pool_model(
+    adt: &Adt,
+    funcs: &[Func],
+    depth: usize,
+    min: i32,
+    max: i32,
+    query: &Query,
+) -> (Vec<Decl>, Vec<String>) {
+    assert!(depth > 0, "Depth bound must be at least 1");
+
+    let pool = Pool {
+        adt,
+        funcs,
+        depth,
+        min,
+    };
+
+    let mut decls = Vec::new();
+    let mut constraints = Vec::new();
+
+    let mut args = Vec::new();
+    for (i, t) in adt.args.iter().enumerate() {
+        let domain = match t {
+            Type::Int => format!("int({min}..{max})"),
+            Type::Bool => "bool".to_string(),
+            Type::Custom(_) => {
+                args.push(Bound::Slot(i, 1));
+                continue;
+            }
+        };
+        decls.push(Decl {
+            name: arg_variable(i),
+            domain,
+        });
+        args.push(Bound::Expr(Expr::Var(arg_variable(i))));
+    }
+
+    let leaf = adt
+        .constructors
+        .iter()
+        .position(|c| c.recursive_fields() == 0);
+    assert!(
+        adt.value_arg().is_none() || leaf.is_some(),
+        "Adt {} has no non-recursive constructor, so it has no finite values",
+        adt.name
+    );
+    let leaf = leaf.unwrap_or(0);
+
+    // each value among the arguments gets a pool of its own
+    for arg in (0..adt.args.len()).filter(|i| matches!(adt.args[*i], Type::Custom(_))) {
+        let tag_var = |slot| adt.slot_variable(arg, "tag", slot);
+        for slot in 1..=adt.slot_count(depth) {
+            decls.push(Decl {
+                name: tag_var(slot),
+                domain: format!("int(1..{})", adt.constructors.len()),
+            });
+
+            for (i, con) in adt.constructors.iter().enumerate() {
+                let tag = adt.tag(&con.prefix);
+                let defaults = pool.field_defaults(i, arg, slot);
+                for (j, t) in con.types.iter().enumerate() {
+                    let domain = match t {
+                        Type::Int => format!("int({min}..{max})"),
+                        Type::Bool => "bool".to_string(),
+                        Type::Custom(_) => continue,
+                    };
+                    decls.push(Decl {
+                        name: adt.slot_variable(arg, &format!("{}_{}", con.prefix, j + 1), slot),
+                        domain,
+                    });
+                }
+
+                // fields of the constructors not in the slot are pinned, as in the flat encoding
+                if !defaults.is_empty() {
+                    constraints.push(format!(
+                        "({} != {tag}) -> ({})",
+                        tag_var(slot),
+                        defaults.join(" /\\ ")
+                    ));
+                }
+
+                if con.recursive_fields() > 0 && pool.level(slot) == depth {
+                    // no room left for children
+                    constraints.push(format!("{} != {tag}", tag_var(slot)));
+                }
+            }
+
+            // children a slot doesn't use are pinned to the first non-recursive constructor
+            if pool.level(slot) < depth {
+                for child in 0..adt.recursive_arity() {
+                    let child_slot = adt.child_slot(slot, child);
+                    let users = adt
+                        .constructors
+                        .iter()
+                        .filter(|c| c.recursive_fields() > child)
+                        .map(|c| format!("{} != {}", tag_var(slot), adt.tag(&c.prefix)))
+                        .collect::<Vec<String>>();
+                    let mut pinned = vec![format!(
+                        "{} = {}",
+                        tag_var(child_slot),
+                        adt.tag(&adt.constructors[leaf].prefix)
+                    )];
+                    pinned.extend(pool.field_defaults(leaf, arg, child_slot));
+                    if users.is_empty() {
+                        constraints.push(pinned.join(" /\\ "));
+                    } else {
+                        constraints.push(format!(
+                            "({}) -> ({})",
+                            users.join(" /\\ "),
+                            pinned.join(" /\\ ")
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    let root = funcs.first().expect("Expected at least one function");
+    let (holds, mut defined) = pool.call(
+        &root.name,
+        &args,
+        &HashMap::new(),
+        &[],
+        MAX_CALL_DEPTH,
+        query.negate,
+    );
+    // whatever the polarity, the Int valued calls must match a clause
+    defined.insert(0, holds);
+    constraints.push(Expr::all(defined).to_string());
+
+    if let Some(con) = query.constructor {
+        let arg = adt
+            .value_arg()
+            .expect("Fixing the constructor needs an Adt argument");
+        constraints.push(format!(
+            "{} = {}",
+            adt.slot_variable(arg, "tag", 1),
+            adt.tag(&adt.constructors[con].prefix)
+        ));
+    }
+
+    (decls, constraints)
+}
+
+impl Pool<'_> {
+    // the level of a slot in the pool, the root being on level 1
+    fn level(&self, slot: usize) -> usize {
+        let mut level = 1;
+        while self.adt.slot_count(level) < slot {
+            level += 1;
+        }
+        level
+    }
+
+    fn field_defaults(&self, con: usize, arg: usize, slot: usize) -> Vec<String> {
+        let con = &self.adt.constructors[con];
+        let field = |j: usize| {
+            self.adt
+                .slot_variable(arg, &format!("{}_{}", con.prefix, j + 1), slot)
+        };
+        con.types
+            .iter()
+            .enumerate()
+            .filter_map(|(j, t)| match t {
+                Type::Int => Some(format!("{} = {}", field(j), self.min)),
+                Type::Bool => Some(format!("{} = false", field(j))),
+                Type::Custom(_) => None,
+            })
+            .collect()
+    }
+
+    // binds the fields of a constructor at a slot to the variables of its pattern, the condition
+    // being that the slot holds the constructor with the literals of its pattern.
+    // returns None when the constructor can't be in the slot
+    fn match_con(
+        &self,
+        pattern: &FuncInput,
+        arg: usize,
+        slot: usize,
+        env: &mut HashMap<String, Bound>,
+    ) -> Option<Expr> {
+        let prefix = &pattern.prefix;
+        let tag = self.adt.tag(prefix);
+        let con = self.adt.tagged(tag);
+        if con.recursive_fields() > 0 && self.level(slot) == self.depth {
+            return None;
+        }
+        assert!(
+            pattern.input.len() == con.types.len(),
+            "Pattern for {prefix} binds {} variables, expected {}",
+            pattern.input.len(),
+            con.types.len()
+        );
+
+        let field = |j: usize| {
+            Expr::Var(
+                self.adt
+                    .slot_variable(arg, &format!("{prefix}_{}", j + 1), slot),
+            )
+        };
+        let mut child = 0;
+        for (j, (name, t)) in pattern.input.iter().zip(&con.types).enumerate() {
+            let bound = match t {
+                Type::Custom(_) => {
+                    child += 1;
+                    Bound::Slot(arg, self.adt.child_slot(slot, child - 1))
+                }
+                _ => Bound::Expr(field(j)),
+            };
+            env.insert(name.clone(), bound);
+        }
+
+        let mut conds = vec![Expr::bin(
+            BinOp::Eq,
+            Expr::Var(self.adt.slot_variable(arg, "tag", slot)),
+            Expr::Int(i32::try_from(tag).expect("the tag fits in an Int")),
+        )];
+        conds.extend(
+            pattern
+                .literals()
+                .iter()
+                .map(|(j, value)| Expr::bin(BinOp::Eq, field(*j), Expr::value(value))),
+        );
+        Some(Expr::all(conds))
+    }
+
+    // unrolls a call to the named function, the result is the disjunction of the clauses that can match,
+    // each on the values the clauses above it don't match, as haskell takes the first that does.
+    // negating the bodies rather than the whole call keeps the values that match no clause out.
+    // a function giving an Int sums the bodies instead, weighted by whether their clause applies,
+    // and as haskell fails when none does, gives with it the conditions for the call to match one.
+    // this is stricter than haskell, which may never evaluate the call
+    fn call(
+        &self,
+        name: &str,
+        args: &[Bound],
+        env: &HashMap<String, Bound>,
+        scope: &[Func],
+        fuel: usize,
+        negate: bool,
+    ) -> (Expr, Vec<Expr>) {
+        assert!(
+            fuel > 0,
+            "Call to {name} does not terminate within the depth bound"
+        );
+
+        let local = scope.iter().any(|f| f.name == name);
+        let clauses = if local {
+            scope
+                .iter()
+                .filter(|f| f.name == name)
+                .collect::<Vec<&Func>>()
+        } else {
+            self.funcs
+                .iter()
+                .filter(|f| f.name == name)
+                .collect::<Vec<&Func>>()
+        };
+        assert!(!clauses.is_empty(), "Function {name} not found");
+
+        let all_patterns = clauses
+            .iter()
+            .map(|clause| {
+                if local {
+                    clause.args.clone()
+                } else {
+                    clause.patterns()
+                }
+            })
+            .collect::<Vec<Vec<Pattern>>>();
+
+        let mut options = Vec::new();
+        // the patterns of the clauses above, and the condition for a value to match them
+        let mut above: Vec<(&[Pattern], Expr)> = Vec::new();
+        'clauses: for (clause, patterns) in clauses.into_iter().zip(&all_patterns) {
+            assert!(
+                patterns.len() == args.len(),
+                "Function {name} applied to {} arguments, expected {}",
+                args.len(),
+                patterns.len()
+            );
+
+            // local binds see the variables of the clause they belong to
+            let mut clause_env = if local { env.clone() } else { HashMap::new() };
+            let mut conds = Vec::new();
+            for (pattern, arg) in patterns.iter().zip(args) {
+                match (pattern, arg) {
+                    (Pattern::Var(var), _) => match (pattern.literal(), arg) {
+                        (Some(value), Bound::Expr(e)) => {
+                            conds.push(Expr::bin(BinOp::Eq, e.clone(), Expr::value(&value)));
+                        }
+                        (Some(_), Bound::Slot(_, slot)) => {
+                            panic!("Literal pattern {var} applied to the value at slot {slot}")
+                        }
+                        (None, _) => {
+                            clause_env.insert(var.clone(), arg.clone());
+                        }
+                    },
+                    (Pattern::Con(con), Bound::Slot(arg, slot)) => {
+                        match self.match_con(con, *arg, *slot, &mut clause_env) {
+                            Some(cond) => conds.push(cond),
+                            None => continue 'clauses,
+                        }
+                    }
+                    (Pattern::Con(con), Bound::Expr(e)) => {
+                        panic!(
+                            "Constructor pattern {con} applied to non-{} value {e}",
+                            self.adt.name
+                        )
+                    }
+                }
+            }
+
+            let clause_scope = if local {
+                scope
+            } else {
+                &clause.local_binds[..]
+            };
+            let matched = Expr::all(conds);
+            let mut conds = vec![matched.clone()];
+            conds.extend(
+                above
+                    .iter()
+                    .filter(|(earlier, _)| earlier.iter().zip(patterns).all(|(e, p)| e.overlaps(p)))
+                    .map(|(_, m)| Expr::not(m.clone())),
+            );
+            // the calls the body makes must match a clause when this one applies
+            let mut inner = Vec::new();
+            let body = self.expr(&clause.opp, &clause_env, clause_scope, fuel - 1, &mut inner);
+            options.push((Expr::all(conds), body, inner));
+            above.push((patterns, matched));
+        }
+
+        let int = self.adt.returns_int(name, self.funcs, scope);
+        let mut defined = Vec::new();
+        if int && !self.adt.exhaustive(&all_patterns) {
+            defined.push(Expr::any(
+                options
+                    .iter()
+                    .map(|(cond, _, inner)| Expr::all([vec![cond.clone()], inner.clone()].concat()))
+                    .collect(),
+            ));
+        } else {
+            defined.extend(
+                options
+                    .iter()
+                    .filter(|(_, _, inner)| !inner.is_empty())
+                    .map(|(cond, _, inner)| {
+                        Expr::bin(BinOp::Implies, cond.clone(), Expr::all(inner.clone()))
+                    }),
+            );
+        }
+
+        let options = options.into_iter().map(|(cond, body, _)| (cond, body));
+        let value = if int {
+            Expr::cases(options.collect())
+        } else {
+            Expr::any(
+                options
+                    .map(|(cond, body)| {
+                        Expr::all(vec![cond, if negate { Expr::not(body) } else { body }])
+                    })
+                    .collect(),
+            )
+        };
+        (value, defined)
+    }
+
+    // `defined` gathers the conditions for the calls in the expression to match a clause
+    fn expr(
+        &self,
+        op: &Operation,
+        env: &HashMap<String, Bound>,
+        scope: &[Func],
+        fuel: usize,
+        defined: &mut Vec<Expr>,
+    ) -> Expr {
+        match self.bound(op, env, scope, fuel, defined) {
+            Bound::Expr(e) => e,
+            Bound::Slot(_, slot) => {
+                panic!("Expected an Int or Bool expression, found the value at slot {slot} in {op}")
+            }
+        }
+    }
+
+    fn bound(
+        &self,
+        op: &Operation,
+        env: &HashMap<String, Bound>,
+        scope: &[Func],
+        fuel: usize,
+        defined: &mut Vec<Expr>,
+    ) -> Bound {
+        let bin = |l: &Operation, r: &Operation, op: BinOp, defined: &mut Vec<Expr>| {
+            Bound::Expr(Expr::bin(
+                op,
+                self.expr(l, env, scope, fuel, defined),
+                self.expr(r, env, scope, fuel, defined),
+            ))
+        };
+        let call = |name: &str, args: &[Bound], defined: &mut Vec<Expr>| {
+            let (value, conds) = self.call(name, args, env, scope, fuel, false);
+            defined.extend(conds);
+            Bound::Expr(value)
+        };
+        match op {
+            Operation::BoolLit(b) => Bound::Expr(Expr::Bool(*b)),
+            Operation::IntLit(i) => Bound::Expr(Expr::Int(*i)),
+            Operation::Var(name) => match env.get(name) {
+                Some(bound) => bound.clone(),
+                None => call(name, &[], defined),
+            },
+            Operation::And(l, r) => bin(l, r, BinOp::And, defined),
+            Operation::Or(l, r) => bin(l, r, BinOp::Or, defined),
+            Operation::Implies(l, r) => bin(l, r, BinOp::Implies, defined),
+            Operation::Not(o) => Bound::Expr(Expr::not(self.expr(o, env, scope, fuel, defined))),
+            Operation::Gt(l, r) => bin(l, r, BinOp::Gt, defined),
+            Operation::Lt(l, r) => bin(l, r, BinOp::Lt, defined),
+            Operation::Eq(l, r) => bin(l, r, BinOp::Eq, defined),
+            Operation::Neq(l, r) => bin(l, r, BinOp::Neq, defined),
+            Operation::Leq(l, r) => bin(l, r, BinOp::Leq, defined),
+            Operation::Geq(l, r) => bin(l, r, BinOp::Geq, defined),
+            Operation::Add(l, r) => bin(l, r, BinOp::Add, defined),
+            Operation::Sub(l, r) => bin(l, r, BinOp::Sub, defined),
+            Operation::Mul(l, r) => bin(l, r, BinOp::Mul, defined),
+            Operation::Apply(_, _) => {
+                let Some((name, args)) = op.spine() else {
+                    panic!("Expected a function name at the head of {op}");
+                };
+                let args = args
+                    .into_iter()
+                    .map(|arg| self.bound(arg, env, scope, fuel, defined))
+                    .collect::<Vec<Bound>>();
+                call(name, &args, defined)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adt::constrain_results;
+    use crate::parse::parser::parse;
+
+    #[test]
+    fn int_valued_recursion_sums_the_clauses() {
+        let src = "data Tree = Leaf | Node Int Tree Tree
+
+size :: Tree -> Int
+size Leaf = 0
+size (Node v l r) = 1 + size l + size r
+";
+        let (adt, funcs) = parse(src, "hs", None, None, false);
+        let funcs = constrain_results(&funcs, 2, 2);
+        let query = Query {
+            count: 1,
+            negate: false,
+            constructor: None,
+            objective: None,
+            seed: None,
+            hashes: Vec::new(),
+            variant: false,
+        };
+        let (_, constraints) = pool_model(&adt, &funcs, 3, 0, 3, &query);
+        // the recursive calls go to the unconstrained clauses, each a sum over the cases
+        assert_eq!(
+            constraints.last().unwrap(),
+            "tag_1 = 1 /\\ 0 = 2 \\/ tag_1 = 2 /\\ 1 \
+             + toInt(tag_2 = 1) * 0 + toInt(tag_2 = 2) * (1 + toInt(tag_4 = 1) * 0 + toInt(tag_5 = 1) * 0) \
+             + toInt(tag_3 = 1) * 0 + toInt(tag_3 = 2) * (1 + toInt(tag_6 = 1) * 0 + toInt(tag_7 = 1) * 0) = 2"
+        );
+    }
+
+    #[test]
+    fn int_valued_calls_must_match_a_clause() {
+        let src = "data Tree = Leaf | Node Int Tree Tree
+
+heavy :: Tree -> Bool
+heavy Leaf = False
+heavy (Node v l r) = weight v > 2
+  where
+    weight 0 = 1
+    weight 1 = 5
+";
+        let (adt, funcs) = parse(src, "hs", None, None, false);
+        let query = Query {
+            count: 1,
+            negate: true,
+            constructor: None,
+            objective: None,
+            seed: None,
+            hashes: Vec::new(),
+            variant: false,
+        };
+        let (_, constraints) = pool_model(&adt, &funcs, 2, 0, 3, &query);
+        // negated or not, a v weight has no clause for is out, as haskell fails on it
+        assert_eq!(
+            constraints.last().unwrap(),
+            "(tag_1 = 1 \\/ tag_1 = 2 /\\ !(toInt(Node_1_1 = 0) * 1 + toInt(Node_1_1 = 1) * 5 > 2)) \
+             /\\ (tag_1 = 2 -> Node_1_1 = 0 \\/ Node_1_1 = 1)"
+        );
+    }
+}
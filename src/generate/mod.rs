@@ -1,3 +1,6 @@
 pub mod codegen;
 mod essence;
-//mod oxide_out;
+mod essence_expr;
+mod essence_pool;
+#[cfg(feature = "oxide")]
+mod oxide_out;
@@ -1,17 +1,14 @@
-use core::panic;
-use std::io::Write;
+use std::collections::HashMap;
 use std::{
     cell::RefCell,
-    collections::HashMap,
     rc::Rc,
     sync::{Arc, Mutex, RwLock},
 };
 
-use crate::adt::{Adt, Func, Operand};
+use crate::adt::{Adt, Bounds, Func, FuncInput, Operation, Pattern, Query, Type, Value};
 use conjure_cp_core::{
     Model,
     ast::{Atom, DeclarationPtr, Domain, Expression, Literal, Metadata, Moo, Name, SymbolTable},
-    conjure_cp_core,
     context::Context,
     error::Error,
     into_matrix_expr, range,
@@ -24,7 +21,49 @@ use conjure_cp_core::{
 };
 use ustr::Ustr;
 
-pub fn generate_oxide_output(adt: &Adt, funcs: &[Func], verbose: bool) -> String {
+/// Builds the model in process with conjure-cp-core and solves it with Minion.
+/// Returns up to `query.count` distinct solutions as (variable, value) pairs, like `parse_essence_output`.
+/// # Panics
+/// Panics if the Adt is recursive, the predicate takes several arguments or has a local bind giving an Int,
+/// or if rewriting or solving the model fails.
+pub fn generate_oxide_output(
+    adt: &Adt,
+    funcs: &[Func],
+    bounds: &Bounds,
+    query: &Query,
+    verbose: bool,
+) -> Vec<Vec<(String, String)>> {
+    let Bounds { min, max, .. } = *bounds;
+    assert!(
+        !adt.is_recursive(),
+        "The oxide backend does not support recursive Adts yet"
+    );
+    assert!(
+        adt.is_single_value(),
+        "The oxide backend does not support predicates with several arguments yet"
+    );
+    assert!(
+        query.objective.is_none(),
+        "The oxide backend does not support objectives yet"
+    );
+    assert!(
+        query.seed.is_none() && query.hashes.is_empty(),
+        "The oxide backend does not support randomised search yet"
+    );
+    // local binds are inlined as the disjunction of their clauses, which only holds for Bools
+    for func in funcs {
+        if let Some(bind) = func
+            .local_binds
+            .iter()
+            .find(|b| adt.returns_int(&b.name, &[], &func.local_binds))
+        {
+            panic!(
+                "The oxide backend does not support local binds giving an Int yet: {} in {}",
+                bind.name, func.name
+            );
+        }
+    }
+
     if verbose {
         println!("--- Generating Oxide Output ---");
         println!("--- ADT ---");
@@ -35,7 +74,7 @@ pub fn generate_oxide_output(adt: &Adt, funcs: &[Func], verbose: bool) -> String
         }
     }
 
-    let model = create_model(adt, funcs, Arc::default(), verbose);
+    let model = create_model(adt, funcs, Arc::default(), min, max, query);
 
     if verbose {
         println!("--- Generated Model ---");
@@ -45,24 +84,34 @@ pub fn generate_oxide_output(adt: &Adt, funcs: &[Func], verbose: bool) -> String
     let rule_sets = resolve_rule_sets(SolverFamily::Minion, &[]).unwrap();
 
     let model = rewrite_naive(&model, &rule_sets, true, false).unwrap();
-    println!("Rewritten model: \n {model} \n",);
+    if verbose {
+        println!("Rewritten model: \n {model} \n",);
+    }
 
     let solver = Solver::new(adaptors::Minion::new());
-    let solver = solver.load_model(model.clone()).unwrap();
-
-    let counter_ptr = Arc::new(Mutex::new(0));
-    let counter_ptr_2 = counter_ptr.clone();
+    let solver = solver.load_model(model).unwrap();
 
-    let all_solutions_ptr = Arc::new(Mutex::<Vec<HashMap<Name, Literal>>>::new(vec![]));
+    let count = query.count;
+    let all_solutions_ptr = Arc::new(Mutex::<Vec<Vec<(String, String)>>>::new(vec![]));
     let all_solutions_ptr_2 = all_solutions_ptr.clone();
 
     let result = solver.solve(Box::new(move |sols| {
-        let mut counter = (*counter_ptr_2).lock().unwrap();
-        *counter += 1;
-
         let mut all_solutions = (*all_solutions_ptr_2).lock().unwrap();
-        (*all_solutions).push(sols);
-        true
+        // only the variables we declared, not the ones introduced by rewriting,
+        // which can differ between solutions that are the same value
+        let mut assignments = sols
+            .iter()
+            .filter_map(|(k, v)| match k {
+                Name::User(name) => Some((name.to_string(), v.to_string())),
+                _ => None,
+            })
+            .collect::<Vec<(String, String)>>();
+        assignments.sort();
+        if !(*all_solutions).contains(&assignments) {
+            (*all_solutions).push(assignments);
+        }
+        // keep searching until we have enough
+        (*all_solutions).len() < count
     }));
 
     // Did the solver run successfully?
@@ -73,76 +122,72 @@ pub fn generate_oxide_output(adt: &Adt, funcs: &[Func], verbose: bool) -> String
         }
     };
 
-    // Read our counter.
-    let counter = (*counter_ptr).lock().unwrap();
-    println!("Num solutions: {counter}\n");
+    let all_sols = (*all_solutions_ptr).lock().unwrap().clone();
 
-    // Read solutions, print 3
-    let all_sols = (*all_solutions_ptr).lock().unwrap();
-    for (i, sols) in all_sols.iter().enumerate() {
-        if i > 2 {
-            println!("... and {} more", *counter - i);
-            break;
-        }
-        println!("Solution {}:", i + 1);
-        for (k, v) in sols {
-            println!("  {k} = {v}");
+    if verbose {
+        for (i, assignments) in all_sols.iter().enumerate() {
+            for (var, val) in assignments {
+                println!("Oxide assignment {}: {var} = {val}", i + 1);
+            }
         }
-        println!();
     }
-    println!();
 
-    println!("--- Oxide Specification ---");
-
-    // write oxide specification to file
-    let mut file = std::fs::File::create("output.oxide").expect("Could not create output file");
-    file.write_all(format!("{model}").as_bytes())
-        .expect("Could not write to output file");
-
-    "output.oxide".to_string()
+    all_sols
 }
 
 fn create_model(
     adt: &Adt,
     funcs: &[Func],
     context: Arc<RwLock<Context<'static>>>,
-    verbose: bool,
+    min: i32,
+    max: i32,
+    query: &Query,
 ) -> Model {
     let mut model = Model::new(context);
     let submodel = model.as_submodel_mut();
 
     // Add ADT to model
-    parse_adt_to_model(adt, &mut submodel.symbols_mut());
+    parse_adt_to_model(adt, &mut submodel.symbols_mut(), min, max);
 
-    let constraints: Vec<Expression> = funcs
+    let symbols = model.as_submodel_mut().symbols_ptr_unchecked().clone();
+    let clauses: Vec<Expression> = funcs
         .iter()
-        .map(|x| {
-            convert_function(
-                adt,
-                x,
-                model.as_submodel_mut().symbols_ptr_unchecked(),
-                verbose,
-            )
-        })
+        .enumerate()
+        .map(|(i, x)| convert_function(adt, x, &funcs[..i], &symbols, min, query.negate))
         .collect();
+
+    // the value satisfies the predicate if one of the clauses holds
+    let constraint = Expression::Or(Metadata::new(), Moo::new(into_matrix_expr!(clauses)));
+    let mut constraints = vec![constraint];
+
+    if let Some(con) = query.constructor {
+        constraints.push(Expression::Eq(
+            Metadata::new(),
+            Moo::new(reference(&symbols, "tag")),
+            Moo::new(literal(Literal::Int(
+                i32::try_from(adt.tag(&adt.constructors[con].prefix)).expect("overflow"),
+            ))),
+        ));
+    }
     model.as_submodel_mut().add_constraints(constraints);
 
     model
 }
 
-fn parse_adt_to_model(adt: &Adt, symbols: &mut SymbolTable) {
+fn parse_adt_to_model(adt: &Adt, symbols: &mut SymbolTable, min: i32, max: i32) {
     let cons = adt.constructors.clone();
 
     for con in cons {
         let con_name = con.prefix;
         for (i, t) in con.types.iter().enumerate() {
-            let var_name = format!("{con_name}_{i}");
+            let var_name = format!("{con_name}_{}", i + 1);
 
             let name = Name::User(Ustr::from(&var_name));
 
             let domain = match t {
-                crate::adt::Type::Int => Domain::Int([range!(1..5)].to_vec()), // Example range
-                crate::adt::Type::Bool => Domain::Bool,
+                Type::Int => Domain::Int([range!(min..max)].to_vec()),
+                Type::Bool => Domain::Bool,
+                Type::Custom(_) => unreachable!("recursive Adts are rejected before this"),
             };
 
             let _ = symbols
@@ -164,148 +209,223 @@ fn parse_adt_to_model(adt: &Adt, symbols: &mut SymbolTable) {
         )));
 }
 
+fn reference(symbols: &Rc<RefCell<SymbolTable>>, var_name: &str) -> Expression {
+    let name = Name::User(Ustr::from(var_name));
+    let declaration: DeclarationPtr = symbols
+        .borrow()
+        .lookup(&name)
+        .unwrap_or_else(|| panic!("Could not find declaration for {var_name}"));
+
+    Expression::Atomic(
+        Metadata::new(),
+        Atom::Reference(conjure_cp_core::ast::Reference { ptr: declaration }),
+    )
+}
+
+fn literal(lit: Literal) -> Expression {
+    Expression::Atomic(Metadata::new(), Atom::Literal(lit))
+}
+
+// tag = i /\ body (negated for counterexamples) /\ (the fields of the other constructors are pinned),
+// with the literals of the clause's pattern, and not those of the clauses above it on the constructor
 fn convert_function(
     adt: &Adt,
     func: &Func,
+    above: &[Func],
     symbols: &Rc<RefCell<SymbolTable>>,
-    verbose: bool,
+    min: i32,
+    negate: bool,
 ) -> Expression {
-    let prefixes = adt
-        .constructors
-        .iter()
-        .map(|c| c.prefix.clone())
-        .collect::<Vec<String>>();
+    let tag = adt.tag(&func.con.prefix);
+    let func_con = adt.tagged(tag);
 
-    let func_con = adt
-        .constructors
+    let env = func
+        .con
+        .input
         .iter()
-        .find(|c| c.prefix == func.con.prefix)
-        .expect("Function input constructor not found in ADT");
-
-    let _not_prefixes = prefixes
-        .iter()
-        .filter(|p| *p != &func.con.prefix)
-        .collect::<Vec<&String>>();
-    //TODO, ADD THE NOTS
-
-    // Build expression
-    match &func.opp {
-        crate::adt::Operation::ConstSelf => {
-            let name = func.con.prefix.clone();
-            let prefix = Name::User(Ustr::from(&format!("{name}_0"))); // hard coding as a test TODO FIX
-
-            let declaration: DeclarationPtr = symbols
-                .borrow()
-                .lookup(&prefix)
-                .expect("Could not find declaration for function input");
-
-            let name_expr = Expression::Atomic(
-                Metadata::new(),
-                Atom::Reference(conjure_cp_core::ast::Reference { ptr: declaration }),
-            );
-
-            //right now const self just assumes boolean true, later this should be extended to be a general constant
-            let const_self =
-                Expression::Atomic(Metadata::new(), Atom::Literal(Literal::Bool(true)));
-
-            let eq_expr =
-                Expression::Eq(Metadata::new(), Moo::new(name_expr), Moo::new(const_self));
+        .enumerate()
+        .map(|(i, name)| {
+            (
+                name.clone(),
+                reference(symbols, &format!("{}_{}", func_con.prefix, i + 1)),
+            )
+        })
+        .collect::<HashMap<String, Expression>>();
 
-            // now we have to add this inside an and with the tag constraints
+    let body = convert_operation(&func.opp, &env, &func.local_binds);
+    let body = if negate {
+        Expression::Not(Metadata::new(), Moo::new(body))
+    } else {
+        body
+    };
 
-            let tag_name = Name::User(Ustr::from("tag"));
-            let tag_declaration: DeclarationPtr = symbols
-                .borrow()
-                .lookup(&tag_name)
-                .expect("Could not find declaration for tag variable");
+    let mut constraints = vec![
+        Expression::Eq(
+            Metadata::new(),
+            Moo::new(reference(symbols, "tag")),
+            Moo::new(literal(Literal::Int(i32::try_from(tag).expect("overflow")))),
+        ),
+        body,
+    ];
+
+    constraints.extend(pattern_literals(&func.con, symbols));
+    for earlier in above.iter().filter(|f| f.con.overlaps(&func.con)) {
+        let matched = pattern_literals(&earlier.con, symbols);
+        // a clause above matching any value of the constructor leaves none for this one
+        let matched = if matched.is_empty() {
+            literal(Literal::Bool(true))
+        } else {
+            Expression::And(Metadata::new(), Moo::new(into_matrix_expr!(matched)))
+        };
+        constraints.push(Expression::Not(Metadata::new(), Moo::new(matched)));
+    }
 
-            let tag_expr = Expression::Eq(
+    for con in adt
+        .constructors
+        .iter()
+        .filter(|c| c.prefix != func_con.prefix)
+    {
+        for (j, t) in con.types.iter().enumerate() {
+            let default = match t {
+                Type::Int => Literal::Int(min),
+                Type::Bool => Literal::Bool(false),
+                Type::Custom(_) => unreachable!("recursive Adts are rejected before this"),
+            };
+            constraints.push(Expression::Eq(
                 Metadata::new(),
-                Moo::new(Expression::Atomic(
-                    Metadata::new(),
-                    Atom::Reference(conjure_cp_core::ast::Reference {
-                        ptr: tag_declaration,
-                    }),
-                )),
-                Moo::new(Expression::Atomic(
-                    Metadata::new(),
-                    Atom::Literal(Literal::Int(
-                        i32::try_from(prefixes.iter().position(|p| p == &func.con.prefix).unwrap())
-                            .unwrap(),
-                    )),
-                )),
-            );
-            let constraints = into_matrix_expr!([eq_expr, tag_expr].to_vec());
-
-            Expression::And(Metadata::new(), Moo::new(constraints))
+                Moo::new(reference(symbols, &format!("{}_{}", con.prefix, j + 1))),
+                Moo::new(literal(default)),
+            ));
         }
-        crate::adt::Operation::Lt(l, r) => {
-            if verbose {
-                println!("Converting Lt function: {func:?}");
-            }
-            if let Operand::Var(_) = l {
-                let prefix = Name::User(Ustr::from(&format!("{}_0", func_con.prefix))); // hard coding as a test TODO FIX
-
-                println!("Prefix for Lt left operand: {prefix:?}");
-
-                let declaration: DeclarationPtr = symbols
-                    .borrow()
-                    .lookup(&prefix)
-                    .expect("Could not find declaration for function input");
-
-                let name_expr = Expression::Atomic(
-                    Metadata::new(),
-                    Atom::Reference(conjure_cp_core::ast::Reference { ptr: declaration }),
-                );
+    }
 
-                let const_lit = match r {
-                    Operand::Lit(i) => Literal::Int(*i),
-                    _ => panic!("Right operand must be a literal for Lt operation"),
-                };
+    Expression::And(Metadata::new(), Moo::new(into_matrix_expr!(constraints)))
+}
 
-                let const_expr = Expression::Atomic(Metadata::new(), Atom::Literal(const_lit));
+// the fields of the constructor equal to the literal sub-patterns of the pattern
+fn pattern_literals(con: &FuncInput, symbols: &Rc<RefCell<SymbolTable>>) -> Vec<Expression> {
+    con.literals()
+        .into_iter()
+        .map(|(j, value)| {
+            Expression::Eq(
+                Metadata::new(),
+                Moo::new(reference(symbols, &format!("{}_{}", con.prefix, j + 1))),
+                Moo::new(literal_pattern(value)),
+            )
+        })
+        .collect()
+}
 
-                let lt_expr =
-                    Expression::Lt(Metadata::new(), Moo::new(name_expr), Moo::new(const_expr));
+fn literal_pattern(value: Value) -> Expression {
+    literal(match value {
+        Value::Int(i) => Literal::Int(i),
+        Value::Bool(b) => Literal::Bool(b),
+        _ => unreachable!("literal patterns are Ints or Bools"),
+    })
+}
 
-                // now we have to add this inside an and with the tag constraints
+fn convert_operation(
+    op: &Operation,
+    env: &HashMap<String, Expression>,
+    local_binds: &[Func],
+) -> Expression {
+    let conv = |o: &Operation| Moo::new(convert_operation(o, env, local_binds));
+    let list = |l: &Operation, r: &Operation| {
+        Moo::new(into_matrix_expr!(vec![
+            convert_operation(l, env, local_binds),
+            convert_operation(r, env, local_binds)
+        ]))
+    };
 
-                let tag_name = Name::User(Ustr::from("tag"));
-                let tag_declaration: DeclarationPtr = symbols
-                    .borrow()
-                    .lookup(&tag_name)
-                    .expect("Could not find declaration for tag variable");
+    match op {
+        Operation::BoolLit(b) => literal(Literal::Bool(*b)),
+        Operation::IntLit(i) => literal(Literal::Int(*i)),
+        Operation::Var(name) => match env.get(name) {
+            Some(e) => e.clone(),
+            None => apply(op, env, local_binds),
+        },
+        Operation::And(l, r) => Expression::And(Metadata::new(), list(l, r)),
+        Operation::Or(l, r) => Expression::Or(Metadata::new(), list(l, r)),
+        Operation::Implies(l, r) => Expression::Imply(Metadata::new(), conv(l), conv(r)),
+        Operation::Not(o) => Expression::Not(Metadata::new(), conv(o)),
+        Operation::Gt(l, r) => Expression::Gt(Metadata::new(), conv(l), conv(r)),
+        Operation::Lt(l, r) => Expression::Lt(Metadata::new(), conv(l), conv(r)),
+        Operation::Eq(l, r) => Expression::Eq(Metadata::new(), conv(l), conv(r)),
+        Operation::Neq(l, r) => Expression::Neq(Metadata::new(), conv(l), conv(r)),
+        Operation::Leq(l, r) => Expression::Leq(Metadata::new(), conv(l), conv(r)),
+        Operation::Geq(l, r) => Expression::Geq(Metadata::new(), conv(l), conv(r)),
+        Operation::Add(l, r) => Expression::Sum(Metadata::new(), list(l, r)),
+        Operation::Mul(l, r) => Expression::Product(Metadata::new(), list(l, r)),
+        Operation::Sub(l, r) => Expression::Minus(Metadata::new(), conv(l), conv(r)),
+        Operation::Apply(_, _) => apply(op, env, local_binds),
+    }
+}
 
-                let tag_expr = Expression::Eq(
+// inlines a call to a local bind, the disjunction of its clauses with the arguments substituted,
+// each only when its literals match and those of the clauses above it don't
+fn apply(op: &Operation, env: &HashMap<String, Expression>, local_binds: &[Func]) -> Expression {
+    let Some((name, args)) = op.spine() else {
+        panic!("Expected a function name at the head of {op}");
+    };
+    let args = args
+        .into_iter()
+        .map(|arg| convert_operation(arg, env, local_binds))
+        .collect::<Vec<Expression>>();
+    let mut clauses = Vec::new();
+    // the patterns of the clauses above, and the literals the arguments match them on
+    let mut above: Vec<(&[Pattern], Vec<Expression>)> = Vec::new();
+    for fb in local_binds.iter().filter(|fb| fb.name == name) {
+        assert!(
+            fb.args.len() == args.len(),
+            "Function {name} applied to {} arguments, expected {}",
+            args.len(),
+            fb.args.len()
+        );
+        let mut clause_env = env.clone();
+        let mut matched = Vec::new();
+        for (pattern, arg) in fb.args.iter().zip(&args) {
+            match (pattern, pattern.literal()) {
+                (_, Some(value)) => matched.push(Expression::Eq(
                     Metadata::new(),
-                    Moo::new(Expression::Atomic(
-                        Metadata::new(),
-                        Atom::Reference(conjure_cp_core::ast::Reference {
-                            ptr: tag_declaration,
-                        }),
-                    )),
-                    Moo::new(Expression::Atomic(
-                        Metadata::new(),
-                        Atom::Literal(Literal::Int(
-                            i32::try_from(
-                                prefixes.iter().position(|p| p == &func.con.prefix).unwrap(),
-                            )
-                            .unwrap(),
-                        )),
-                    )),
-                );
-                let constraints = into_matrix_expr!([lt_expr, tag_expr].to_vec());
-
-                Expression::And(Metadata::new(), Moo::new(constraints))
-            } else {
-                panic!("Left operand must be a variable for Lt operation");
+                    Moo::new(arg.clone()),
+                    Moo::new(literal_pattern(value)),
+                )),
+                (Pattern::Var(var), None) => {
+                    clause_env.insert(var.clone(), arg.clone());
+                }
+                (Pattern::Con(con), None) => {
+                    panic!("Constructor pattern {con} in a local bind of a non-recursive Adt")
+                }
             }
         }
-        crate::adt::Operation::Gt(_, _) => unimplemented!(),
-        crate::adt::Operation::Eq(_, _) => unimplemented!(),
-        crate::adt::Operation::Neq(_, _) => unimplemented!(),
-        crate::adt::Operation::Leq(_, _) => unimplemented!(),
-        crate::adt::Operation::Geq(_, _) => unimplemented!(),
-        crate::adt::Operation::Add(_, _) => unimplemented!(),
+
+        let mut constraints = matched.clone();
+        for (_, earlier) in above
+            .iter()
+            .filter(|(earlier, _)| earlier.iter().zip(&fb.args).all(|(e, p)| e.overlaps(p)))
+        {
+            // a clause above matching any arguments leaves none for this one
+            let earlier = if earlier.is_empty() {
+                literal(Literal::Bool(true))
+            } else {
+                Expression::And(
+                    Metadata::new(),
+                    Moo::new(into_matrix_expr!(earlier.clone())),
+                )
+            };
+            constraints.push(Expression::Not(Metadata::new(), Moo::new(earlier)));
+        }
+        constraints.push(convert_operation(&fb.opp, &clause_env, local_binds));
+        clauses.push(Expression::And(
+            Metadata::new(),
+            Moo::new(into_matrix_expr!(constraints)),
+        ));
+        above.push((&fb.args, matched));
     }
+
+    assert!(
+        !clauses.is_empty(),
+        "Function {name} not found in local binds"
+    );
+    Expression::Or(Metadata::new(), Moo::new(into_matrix_expr!(clauses)))
 }
@@ -1,11 +1,41 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use randi_check::adt::{Adt, Bounds, Func, Objective, Query, Type, constrain_results};
 use randi_check::generate::codegen;
 use randi_check::parse::parser;
-use randi_check::solve::solve_conjure::solve_conjure;
-use randi_check::validate::parse_solution::parse_essence_output;
+use randi_check::solve::enumerate::solve_enumerate;
+use randi_check::solve::sample::{count_approximate, sample_uniform};
+use randi_check::solve::solve_conjure::{conjure_available, solve_conjure};
+use randi_check::solve::weighted::solve_weighted;
+use randi_check::validate::parse_solution::{decode_value, parse_essence_output};
 
 mod adt;
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Solver {
+    /// solve the generated Essence specification with conjure
+    Conjure,
+    /// enumerate the values within the bounds in process
+    Enumerate,
+    /// build and solve the model in process with conjure-oxide
+    Oxide,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    /// a tag variable and a variable for every field of every constructor
+    Tags,
+    /// a variant with a record of fields for each constructor
+    Variant,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Validator {
+    /// compile and run haskell validation code with ghc
+    Ghc,
+    /// evaluate the predicate in process
+    Eval,
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -13,6 +43,10 @@ struct Args {
     #[arg(value_name = "INPUT", default_value = "input_files/example.hs")]
     input: String,
 
+    /// The predicate to analyse (default: the first one in the file)
+    #[arg(long)]
+    predicate: Option<String>,
+
     /// minimum value for integer variables
     #[arg(long, default_value_t = -10)]
     min: i32,
@@ -22,14 +56,79 @@ struct Args {
     #[arg(long, default_value_t = 10)]
     max: i32,
 
+    /// number of distinct values to generate (default: 1, or 100 tests for a property)
+    #[arg(long)]
+    count: Option<usize>,
+
+    /// maximum depth of values of recursive types
+    #[arg(long, default_value_t = 3)]
+    depth: usize,
+
+    /// Generate values the predicate returns False for, instead of True
+    #[arg(long)]
+    negate: bool,
+
+    /// Solve once per constructor and report which ones the predicate can be satisfied with
+    #[arg(long)]
+    coverage: bool,
+
+    /// Find the smallest and largest value of each Int field of each satisfiable constructor
+    #[arg(long)]
+    boundaries: bool,
+
+    /// Randomise the search, and the generated haskell with -g, reproducibly for the given seed
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Sample the values close to uniformly, using random hash constraints to split the solutions
+    #[arg(long)]
+    sample: bool,
+
+    /// Weight of a constructor when drawing values, eg --weight Node=5, the others default to 1
+    #[arg(long, value_name = "CONSTRUCTOR=WEIGHT", value_parser = parse_weight)]
+    weight: Vec<(String, u32)>,
+
+    /// Count the values satisfying the predicate for each constructor, approximately for large counts
+    #[arg(long)]
+    count_solutions: bool,
+
+    /// Search for a counterexample to the property, or the predicate, in one model
+    #[arg(long)]
+    verify: bool,
+
+    /// Find inputs an Int valued function maps to this value
+    #[arg(long, allow_negative_numbers = true, conflicts_with = "between")]
+    equals: Option<i32>,
+
+    /// Find inputs an Int valued function maps into this range, both ends included
+    #[arg(long, num_args = 2, value_names = ["LOW", "HIGH"], allow_negative_numbers = true)]
+    between: Option<Vec<i32>>,
+
+    /// Search for inputs a predicate returning Either, Maybe, Result or Option fails on with this
+    /// error constructor, or message, instead of ones it succeeds on
+    #[arg(long, value_name = "CONSTRUCTOR")]
+    error: Option<String>,
+
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
 
-    /// Emit oxide output instead of essence
+    /// Solve with conjure-oxide instead of essence, same as --solver oxide
     #[arg(long = "oxide-out")]
     oxide_out: bool,
 
+    /// How to find a solution (default: conjure if it is on the PATH, enumerate otherwise)
+    #[arg(long, value_enum)]
+    solver: Option<Solver>,
+
+    /// How the Essence specification lays out the value, for --solver conjure
+    #[arg(long, value_enum, default_value = "tags")]
+    encoding: Encoding,
+
+    /// How to validate the solution (default: ghc for haskell input, eval otherwise)
+    #[arg(long, value_enum)]
+    validator: Option<Validator>,
+
     ///Create a new haskell file randomly
     #[arg(short, long)]
     generate: bool,
@@ -42,16 +141,37 @@ fn main() {
     let verbose = args.verbose;
     let oxide_out = args.oxide_out;
     let generate = args.generate;
-    let min = args.min;
-    let max = args.max;
+    let bounds = Bounds {
+        min: args.min,
+        max: args.max,
+        depth: args.depth,
+    };
+    let negate = args.negate;
+    let seed = args.seed;
 
-    if min >= max {
+    if bounds.min >= bounds.max {
         eprintln!("Error: Minimum value must be less than maximum value.");
         return;
     }
 
+    if args.count == Some(0) {
+        eprintln!("Error: Count must be at least 1.");
+        return;
+    }
+
+    if args.sample && !args.weight.is_empty() {
+        eprintln!("Error: Sampling and constructor weights cannot be combined.");
+        return;
+    }
+
     if generate {
-        randi_check::random_generation::new_haskell::generate_haskell_random(6, verbose);
+        // without a seed pick one, and print it so the file can be generated again
+        let seed = seed.unwrap_or_else(|| {
+            let seed = rand::random();
+            println!("Seed: {seed}");
+            seed
+        });
+        randi_check::random_generation::new_haskell::generate_haskell_random(6, seed, verbose);
         return;
     }
 
@@ -59,24 +179,514 @@ fn main() {
 
     let filetype = input.split('.').next_back().unwrap_or("");
 
-    let (adt, funcs) = parser::parse(&source_code, filetype, verbose);
+    let (adt, funcs) = parser::parse(
+        &source_code,
+        filetype,
+        args.predicate.as_deref(),
+        args.error.as_deref(),
+        verbose,
+    );
 
-    let spec = codegen::output(&adt, &funcs, oxide_out, verbose, min, max);
+    // a function returning an Int is searched for the inputs that hit the target
+    let target = match (args.equals, &args.between) {
+        (Some(value), _) => Some((value, value)),
+        (None, Some(range)) => Some((range[0], range[1])),
+        (None, None) => None,
+    };
+    let funcs = match (&adt.output, target) {
+        (Type::Int, Some((low, high))) if low <= high => constrain_results(&funcs, low, high),
+        (Type::Int, Some(_)) => {
+            eprintln!("Error: The low end of --between must not be above the high end.");
+            return;
+        }
+        (Type::Int, None) => {
+            eprintln!(
+                "Error: {} returns an Int, give a target with --equals or --between.",
+                funcs[0].name
+            );
+            return;
+        }
+        (_, Some(_)) => {
+            eprintln!(
+                "Error: --equals and --between need a function returning an Int, {} returns a Bool.",
+                funcs[0].name
+            );
+            return;
+        }
+        (_, None) => funcs,
+    };
 
-    solve_conjure(spec, verbose);
+    if adt.value_arg().is_none() && (args.coverage || args.boundaries || !args.weight.is_empty()) {
+        eprintln!(
+            "Error: Coverage, boundaries and weights are per constructor, but {} takes no Adt.",
+            funcs[0].name
+        );
+        return;
+    }
 
-    let assignments = parse_essence_output("output.solution", verbose);
+    // a property is checked on inputs generated from its precondition
+    let property = funcs.iter().any(Func::is_property);
+    let (funcs, property_funcs) = if property {
+        (funcs.iter().map(Func::precondition).collect(), Some(funcs))
+    } else {
+        (funcs, None)
+    };
+    let count = args.count.unwrap_or(if property { 100 } else { 1 });
 
-    let valid = randi_check::validate::gen_haskell::generate_haskell_validation(
-        adt,
-        funcs,
-        &assignments,
-        verbose,
+    if property && negate {
+        eprintln!("Error: --negate does not apply to properties, use the precondition instead.");
+        return;
+    }
+
+    let solver = args.solver.unwrap_or(if oxide_out {
+        Solver::Oxide
+    } else if conjure_available() {
+        Solver::Conjure
+    } else {
+        Solver::Enumerate
+    });
+
+    let validator = args.validator.unwrap_or(if filetype == "hs" {
+        Validator::Ghc
+    } else {
+        Validator::Eval
+    });
+
+    if args.encoding == Encoding::Variant {
+        if solver != Solver::Conjure {
+            eprintln!(
+                "Error: --encoding only applies to the Essence specification of --solver conjure."
+            );
+            return;
+        }
+        if adt.is_pooled() {
+            eprintln!(
+                "Error: The variant encoding needs a predicate on a single non-recursive value, use --encoding tags."
+            );
+            return;
+        }
+        if args.sample || args.count_solutions {
+            eprintln!(
+                "Error: Sampling and counting hash the tag and field variables, which the variant encoding doesn't have."
+            );
+            return;
+        }
+    }
+
+    if solver == Solver::Oxide {
+        if cfg!(not(feature = "oxide")) {
+            eprintln!(
+                "Error: This build has no oxide solver, build it with the oxide feature or use --solver conjure or enumerate."
+            );
+            return;
+        }
+        if adt.is_pooled() {
+            eprintln!(
+                "Error: The oxide solver needs a predicate on a single non-recursive value, use --solver conjure or enumerate."
+            );
+            return;
+        }
+        if seed.is_some() || args.sample || args.boundaries || args.count_solutions {
+            eprintln!(
+                "Error: The oxide solver does not support --seed, --sample, --boundaries or --count-solutions yet."
+            );
+            return;
+        }
+        if target.is_some() {
+            eprintln!(
+                "Error: The oxide solver does not support functions returning an Int, so --equals or --between, yet."
+            );
+            return;
+        }
+    }
+
+    let query = Query {
+        count,
+        negate,
+        constructor: None,
+        objective: None,
+        seed,
+        hashes: Vec::new(),
+        variant: args.encoding == Encoding::Variant,
+    };
+
+    if args.verify {
+        let full = property_funcs.as_ref().unwrap_or(&funcs);
+        verify(&adt, full, &bounds, solver, validator, &query, verbose);
+        return;
+    }
+
+    if args.coverage {
+        coverage(&adt, &funcs, &bounds, solver, validator, &query, verbose);
+        return;
+    }
+
+    if args.boundaries {
+        boundaries(&adt, &funcs, &bounds, solver, validator, &query, verbose);
+        return;
+    }
+
+    if args.count_solutions {
+        count_solutions(&adt, &funcs, &bounds, solver, &query, verbose);
+        return;
+    }
+
+    let weights = if args.weight.is_empty() {
+        None
+    } else {
+        // constructors without a weight get 1
+        let mut weights = vec![1; adt.constructors.len()];
+        for (name, weight) in &args.weight {
+            let Some(con) = adt.constructors.iter().position(|c| &c.prefix == name) else {
+                eprintln!("Error: {} has no constructor {name}.", adt.name);
+                return;
+            };
+            weights[con] = *weight;
+        }
+        Some(weights)
+    };
+
+    let solutions = if let Some(weights) = weights {
+        solve_weighted(&adt, &weights, &query, verbose, |q| {
+            solve(solver, &adt, &funcs, &bounds, q, verbose)
+        })
+    } else if args.sample {
+        sample_uniform(&adt, &bounds, &query, verbose, |q| {
+            solve(solver, &adt, &funcs, &bounds, q, verbose)
+        })
+    } else {
+        solve(solver, &adt, &funcs, &bounds, &query, verbose)
+    };
+
+    if let Some(property_funcs) = &property_funcs {
+        check_property(&adt, property_funcs, &solutions, count, validator, verbose);
+        return;
+    }
+
+    if solutions.is_empty() {
+        println!("No solution exists within the bounds.");
+        return;
+    }
+
+    println!("Generated {} value(s):", solutions.len());
+    let mut failed = 0;
+    for assignments in &solutions {
+        let value = decode_value(&adt, assignments);
+        let valid = validate(validator, &adt, &funcs, assignments, !negate, verbose);
+
+        if valid {
+            println!("  {value}");
+        } else {
+            failed += 1;
+            if negate {
+                println!("  {value}  (does not falsify the predicates)");
+            } else {
+                println!("  {value}  (does not satisfy the predicates)");
+            }
+        }
+    }
+
+    if solutions.len() < count {
+        println!(
+            "Only {} of the {count} requested values exist within the bounds.",
+            solutions.len()
+        );
+    }
+
+    if failed == 0 {
+        if negate {
+            println!("Validation succeeded: All values falsify the predicates.");
+        } else {
+            println!("Validation succeeded: All values satisfy the predicates.");
+        }
+    } else if negate {
+        println!("Validation failed: {failed} value(s) do not falsify the predicates.");
+    } else {
+        println!("Validation failed: {failed} value(s) do not satisfy the predicates.");
+    }
+}
+
+// bounded verification, searching for a value that falsifies the property directly,
+// ie one satisfying pre /\ !post
+fn verify(
+    adt: &Adt,
+    funcs: &[Func],
+    bounds: &Bounds,
+    solver: Solver,
+    validator: Validator,
+    base: &Query,
+    verbose: bool,
+) {
+    let query = Query {
+        count: 1,
+        negate: true,
+        ..base.clone()
+    };
+    let solutions = solve(solver, adt, funcs, bounds, &query, verbose);
+
+    let Some(assignments) = solutions.first() else {
+        println!(
+            "+++ OK, no counterexample with Int fields in {}..{} and depth {}.",
+            bounds.min, bounds.max, bounds.depth
+        );
+        return;
+    };
+
+    let value = decode_value(adt, assignments);
+    if validate(validator, adt, funcs, assignments, false, verbose) {
+        println!("*** Failed! Counterexample:");
+        println!("{value}");
+    } else {
+        println!("*** The solver's counterexample {value} does not falsify the property.");
+    }
+}
+
+// checks the property on each input generated from its precondition, stopping at the first
+// counterexample like QuickCheck
+fn check_property(
+    adt: &Adt,
+    funcs: &[Func],
+    solutions: &[Vec<(String, String)>],
+    count: usize,
+    validator: Validator,
+    verbose: bool,
+) {
+    if solutions.is_empty() {
+        println!("*** Gave up! The precondition can't be satisfied within the bounds.");
+        return;
+    }
+
+    for (i, assignments) in solutions.iter().enumerate() {
+        if !validate(validator, adt, funcs, assignments, true, verbose) {
+            println!(
+                "*** Failed! Falsified (after {} test{}):",
+                i + 1,
+                if i == 0 { "" } else { "s" }
+            );
+            println!("{}", decode_value(adt, assignments));
+            return;
+        }
+    }
+
+    if solutions.len() < count {
+        println!(
+            "+++ OK, passed {} tests, every input satisfying the precondition within the bounds.",
+            solutions.len()
+        );
+    } else {
+        println!("+++ OK, passed {} tests.", solutions.len());
+    }
+}
+
+fn parse_weight(s: &str) -> Result<(String, u32), String> {
+    let (name, weight) = s
+        .split_once('=')
+        .ok_or(format!("Expected CONSTRUCTOR=WEIGHT, found {s}"))?;
+    let weight = weight
+        .parse()
+        .map_err(|_| format!("Weight of {name} is not a non-negative integer"))?;
+    Ok((name.to_string(), weight))
+}
+
+// solves once per constructor with the tag fixed, reporting a witness for each one that can be hit
+fn coverage(
+    adt: &Adt,
+    funcs: &[Func],
+    bounds: &Bounds,
+    solver: Solver,
+    validator: Validator,
+    base: &Query,
+    verbose: bool,
+) {
+    println!("Constructor coverage:");
+    let mut covered = 0;
+    for (i, con) in adt.constructors.iter().enumerate() {
+        let query = Query {
+            count: 1,
+            constructor: Some(i),
+            ..base.clone()
+        };
+        let solutions = solve(solver, adt, funcs, bounds, &query, verbose);
+
+        let Some(assignments) = solutions.first() else {
+            println!("  {}: unsatisfiable within the bounds", con.prefix);
+            continue;
+        };
+        covered += 1;
+        let value = decode_value(adt, assignments);
+        if validate(validator, adt, funcs, assignments, !base.negate, verbose) {
+            println!("  {}: {value}", con.prefix);
+        } else {
+            println!("  {}: {value}  (failed validation)", con.prefix);
+        }
+    }
+    println!(
+        "{covered} of {} constructors are satisfiable.",
+        adt.constructors.len()
     );
+}
+
+// for each Int field of each satisfiable constructor, finds the values with the smallest and largest field
+fn boundaries(
+    adt: &Adt,
+    funcs: &[Func],
+    bounds: &Bounds,
+    solver: Solver,
+    validator: Validator,
+    base: &Query,
+    verbose: bool,
+) {
+    println!("Boundary values:");
+    for (i, con) in adt.constructors.iter().enumerate() {
+        let mut query = Query {
+            count: 1,
+            constructor: Some(i),
+            ..base.clone()
+        };
+        if solve(solver, adt, funcs, bounds, &query, verbose).is_empty() {
+            println!("  {}: unsatisfiable within the bounds", con.prefix);
+            continue;
+        }
+
+        for (j, t) in con.types.iter().enumerate() {
+            if *t != Type::Int {
+                continue;
+            }
+            for maximise in [false, true] {
+                query.objective = Some(Objective { field: j, maximise });
+                let direction = if maximise { "largest" } else { "smallest" };
+                let solutions = solve(solver, adt, funcs, bounds, &query, verbose);
+                let Some(assignments) = solutions.first() else {
+                    continue;
+                };
+                let value = decode_value(adt, assignments);
+                let valid = validate(validator, adt, funcs, assignments, !base.negate, verbose);
+                println!(
+                    "  {} field {} {direction}: {value}{}",
+                    con.prefix,
+                    j + 1,
+                    if valid { "" } else { "  (failed validation)" }
+                );
+            }
+        }
+    }
+}
 
-    if valid {
-        println!("Validation succeeded: The solution satisfies the predicates.");
+// counts the solutions of each constructor against the size of its domain, the ratio being
+// the acceptance rate of generating values at random and filtering them with the predicate
+fn count_solutions(
+    adt: &Adt,
+    funcs: &[Func],
+    bounds: &Bounds,
+    solver: Solver,
+    base: &Query,
+    verbose: bool,
+) {
+    // above this the solutions are no longer listed but counted with hash constraints
+    const EXACT_LIMIT: usize = 1000;
+
+    let ratio = |count: u128, size: u128| {
+        if size == 0 {
+            0.0
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let ratio = count as f64 / size as f64;
+            100.0 * ratio
+        }
+    };
+
+    println!(
+        "Solution counts (Int fields in {}..{}, depth {}):",
+        bounds.min, bounds.max, bounds.depth
+    );
+    let mut total = 0;
+    let mut total_size = 0;
+    let mut exact = true;
+    // without an Adt there are only the totals
+    let constructors = if adt.value_arg().is_some() {
+        (0..adt.constructors.len()).map(Some).collect()
     } else {
-        println!("Validation failed: The solution does not satisfy the predicates.");
+        vec![None]
+    };
+    for constructor in constructors {
+        let query = Query {
+            count: EXACT_LIMIT + 1,
+            constructor,
+            ..base.clone()
+        };
+        let listed = solve(solver, adt, funcs, bounds, &query, verbose).len();
+        let (count, approx) = if listed > EXACT_LIMIT {
+            let count = count_approximate(adt, bounds, &query, verbose, |q| {
+                solve(solver, adt, funcs, bounds, q, verbose)
+            });
+            (count, "~")
+        } else {
+            (listed as u128, "")
+        };
+        exact &= approx.is_empty();
+
+        let size = adt.domain_size(constructor, bounds);
+        total = u128::saturating_add(total, count);
+        total_size = u128::saturating_add(total_size, size);
+        if let Some(con) = constructor {
+            println!(
+                "  {}: {approx}{count} of {size} values ({:.2}%)",
+                adt.constructors[con].prefix,
+                ratio(count, size)
+            );
+        }
+    }
+    println!(
+        "Total: {}{total} of {total_size} values ({:.2}%)",
+        if exact { "" } else { "~" },
+        ratio(total, total_size)
+    );
+}
+
+fn solve(
+    solver: Solver,
+    adt: &Adt,
+    funcs: &[Func],
+    bounds: &Bounds,
+    query: &Query,
+    verbose: bool,
+) -> Vec<Vec<(String, String)>> {
+    match solver {
+        Solver::Conjure => {
+            let spec = codegen::output(adt, funcs, bounds, query, verbose);
+
+            solve_conjure(spec, query.count, query.seed, verbose)
+                .iter()
+                .map(|path| parse_essence_output(path, verbose))
+                .collect()
+        }
+        Solver::Enumerate => solve_enumerate(adt, funcs, bounds, query, verbose),
+        Solver::Oxide => codegen::oxide_output(adt, funcs, bounds, query, verbose),
+    }
+}
+
+fn validate(
+    validator: Validator,
+    adt: &Adt,
+    funcs: &[Func],
+    assignments: &[(String, String)],
+    expected: bool,
+    verbose: bool,
+) -> bool {
+    match validator {
+        Validator::Ghc => randi_check::validate::gen_haskell::generate_haskell_validation(
+            adt.clone(),
+            funcs.to_vec(),
+            assignments,
+            expected,
+            verbose,
+        ),
+        Validator::Eval => randi_check::validate::evaluate::evaluate_validation(
+            adt,
+            funcs,
+            assignments,
+            expected,
+            verbose,
+        ),
     }
 }
@@ -1,19 +1,45 @@
 use tree_sitter::{Node, Tree, TreeCursor};
 
-use crate::adt::{Adt, Cons, Func, FuncInput, Operation, Type};
+use crate::adt::{Adt, Cons, Func, FuncInput, Operation, Pattern, Type};
+use crate::parse::parser::check_error;
 use crate::parse::parser_utils::{
-    print_node, print_nodes, traverse_and_capture, traverse_and_capture_from_node,
+    print_node, print_nodes, reject_whole_value, traverse_and_capture,
+    traverse_and_capture_from_node,
 };
 
 pub fn collect_haskell_adts(tree: &Tree, source_code: &str, verbose: bool) -> Adt {
-    let adt_nodes = traverse_and_capture(tree, "data_type");
+    // the data types that only appear as the errors of a predicate returning Either or Maybe
+    let error_types = traverse_and_capture(tree, "signature")
+        .into_iter()
+        .map(result_type)
+        .filter(|result| is_outcome(*result, source_code))
+        .flat_map(|result| traverse_and_capture_from_node(result, "name"))
+        .map(|n| &source_code[n.start_byte()..n.end_byte()])
+        .collect::<Vec<&str>>();
+
+    let adt_nodes = traverse_and_capture(tree, "data_type")
+        .into_iter()
+        .filter(|node| {
+            node.children(&mut node.walk())
+                .find(|n| n.kind() == "name")
+                .is_none_or(|n| !error_types.contains(&&source_code[n.start_byte()..n.end_byte()]))
+        })
+        .collect::<Vec<Node<'_>>>();
 
     assert!(
-        (adt_nodes.len() == 1),
-        "Expected exactly one Adt in the example file"
+        (adt_nodes.len() <= 1),
+        "Expected at most one Adt besides the error types in the example file"
     );
 
-    let node = &adt_nodes[0];
+    // a predicate on plain Ints and Bools needs no Adt, its arguments are set with the functions
+    let Some(node) = adt_nodes.first() else {
+        return Adt {
+            name: String::new(),
+            constructors: Vec::new(),
+            args: Vec::new(),
+            output: Type::Bool,
+        };
+    };
     if verbose {
         print!("Adt: ");
         let adt_str = &source_code[node.start_byte()..node.end_byte()];
@@ -36,8 +62,9 @@ pub fn collect_haskell_adts(tree: &Tree, source_code: &str, verbose: bool) -> Ad
         }
     }
 
-    // get the constructors of the Adt
-    let constructors = traverse_and_capture_from_node(*node, "data_constructor");
+    // get the constructors of the Adt, captured in reverse order but tagged in declaration order
+    let mut constructors = traverse_and_capture_from_node(*node, "data_constructor");
+    constructors.reverse();
 
     let mut cons_vec: Vec<Cons> = Vec::new();
 
@@ -81,6 +108,8 @@ pub fn collect_haskell_adts(tree: &Tree, source_code: &str, verbose: bool) -> Ad
     let adt = Adt {
         name: name_str.to_string(),
         constructors: cons_vec,
+        args: vec![Type::Custom(name_str.to_string())],
+        output: Type::Bool,
     };
     if verbose {
         println!("Adt: {adt:?}");
@@ -88,170 +117,400 @@ pub fn collect_haskell_adts(tree: &Tree, source_code: &str, verbose: bool) -> Ad
     adt
 }
 
+// what the clauses of the predicate return
+enum Returns<'a> {
+    /// a Bool, or an Int searched for preimages, taken as it is
+    Value(Type),
+    /// an Either or a Maybe, satisfied by success, or by failing with the error searched for
+    Outcome(Option<&'a str>),
+}
+
+/// collects the clauses of the predicate, the first one in the file unless it is named,
+/// and sets the arguments of the Adt to the ones in its signature. functions returning an Int
+/// are taken when named, or when there is no predicate, to search for their preimages.
+/// predicates returning `Either e ()` or `Maybe e` hold on `Right` and `Nothing`,
+/// or when given an error, on the `Left` or `Just` of that error
+/// # Panics
+/// Panics if there is no such predicate, or its signature or clauses aren't supported.
 pub fn collect_haskell_functions(
     tree: &Tree,
     source_code: &str,
-    name: &str,
+    adt: &mut Adt,
+    predicate: Option<&str>,
+    error: Option<&str>,
     verbose: bool,
 ) -> Vec<Func> {
     /* for the purposes of this initial version, I assume that every
     function begins with a signature node, and then every sibling
-    function node after this is a part of it. we also only have
-    one function right now anyway so not much point being super specific
+    function node after this is a part of it
     */
     let sigs = traverse_and_capture(tree, "signature");
+    let mut fallback = None;
+
+    // captured in reverse order, but the first predicate in the file is the default
+    for sig in sigs.into_iter().rev() {
+        let func_name = sig
+            .child(0)
+            .filter(|n| n.kind() == "variable")
+            .map(|n| source_code[n.start_byte()..n.end_byte()].to_string())
+            .expect("Expected the signature to start with the function name");
+
+        // the argument type names from last to first, and the type returned
+        let result = result_type(sig);
+        let inputs = traverse_and_capture_from_node(sig, "name")
+            .iter()
+            .filter(|n| n.end_byte() <= result.start_byte())
+            .map(|n| &source_code[n.start_byte()..n.end_byte()])
+            .collect::<Vec<&str>>();
+        let returns = match &source_code[result.start_byte()..result.end_byte()] {
+            "Bool" | "Property" => Returns::Value(Type::Bool),
+            "Int" => Returns::Value(Type::Int),
+            _ if is_outcome(result, source_code) => Returns::Outcome(error),
+            // skip anything that isn't a predicate, eg main :: IO ()
+            _ => {
+                if verbose {
+                    println!("Skipping {func_name}, it is not a predicate");
+                }
+                continue;
+            }
+        };
+        if inputs.is_empty() || predicate.is_some_and(|p| p != func_name) {
+            continue;
+        }
+        if let (Some(error), Returns::Value(_)) = (error, &returns) {
+            // without a name, look for the first predicate that can fail with the error
+            assert!(
+                predicate.is_none(),
+                "{func_name} doesn't return Either or Maybe, it can't fail with {error}"
+            );
+            continue;
+        }
+        if predicate.is_none() && matches!(returns, Returns::Value(Type::Int)) {
+            fallback = fallback.or(Some((sig, func_name, returns, inputs)));
+            continue;
+        }
+
+        return collect_clauses(
+            source_code,
+            adt,
+            sig,
+            &func_name,
+            &returns,
+            &inputs,
+            verbose,
+        );
+    }
+
+    let Some((sig, func_name, returns, inputs)) = fallback else {
+        panic!(
+            "No clauses found for {}",
+            predicate.unwrap_or("a predicate")
+        );
+    };
+    collect_clauses(
+        source_code,
+        adt,
+        sig,
+        &func_name,
+        &returns,
+        &inputs,
+        verbose,
+    )
+}
+
+// the type a signature returns, after the last arrow
+fn result_type(sig: Node<'_>) -> Node<'_> {
+    let mut result = sig
+        .named_child(sig.named_child_count() - 1)
+        .expect("Expected a type in the signature");
+    while result.kind() == "function" {
+        result = result
+            .named_child(result.named_child_count() - 1)
+            .expect("Expected a type after the arrow");
+    }
+    result
+}
+
+// whether the type is an Either or a Maybe, whose Right or Nothing means success
+fn is_outcome(ty: Node<'_>, source_code: &str) -> bool {
+    let mut head = ty;
+    while head.kind() == "apply" {
+        head = head.named_child(0).expect("Expected a type constructor");
+    }
+    matches!(
+        &source_code[head.start_byte()..head.end_byte()],
+        "Either" | "Maybe"
+    )
+}
+
+// collects the clauses following the signature, the inputs being its argument types from last to first
+fn collect_clauses(
+    source_code: &str,
+    adt: &mut Adt,
+    sig: Node<'_>,
+    func_name: &str,
+    returns: &Returns<'_>,
+    inputs: &[&str],
+    verbose: bool,
+) -> Vec<Func> {
     let mut funcs: Vec<Func> = Vec::new();
+    let types = inputs
+        .iter()
+        .rev()
+        .map(|t| match *t {
+            "Int" => Type::Int,
+            "Bool" => Type::Bool,
+            _ if *t == adt.name => Type::Custom(adt.name.clone()),
+            _ => panic!("Unknown type {t} of an argument of {func_name}"),
+        })
+        .collect::<Vec<Type>>();
+    adt.args = types;
+    adt.output = match returns {
+        Returns::Value(output) => output.clone(),
+        Returns::Outcome(_) => Type::Bool,
+    };
 
-    for sig in sigs {
-        // check if the type in the signature matches the name
+    // get the sibling nodes that are of kind function
+    if verbose {
+        print_nodes(&sig, 0, source_code, false);
+    }
 
-        let names = traverse_and_capture_from_node(sig, "name");
-        assert!(
-            (names.len() == 2),
-            "Expected exactly two names in the signature"
+    let mut functions: Vec<tree_sitter::Node> = Vec::new();
+    let mut sibling = sig.next_sibling();
+    while let Some(node) = sibling.filter(|n| n.kind() == "function") {
+        functions.push(node);
+        sibling = node.next_sibling();
+    }
+    if verbose {
+        println!("Function nodes: {}", functions.len());
+        println!(
+            "Function sources: {:?}",
+            functions
+                .iter()
+                .map(|f| &source_code[f.start_byte()..f.end_byte()])
+                .collect::<Vec<&str>>()
         );
-        let output_type = &source_code[names[0].start_byte()..names[0].end_byte()];
-        let input_name = &source_code[names[1].start_byte()..names[1].end_byte()];
-        assert!((input_name == name), "input doesn't match Adt name");
-        assert!((output_type == "Bool"), "Expected output type to be Bool");
+    }
+    // the errors the clauses fail with, to check the one searched for against
+    let mut failures = Vec::new();
+    for function in functions {
+        let patterns = traverse_and_capture_from_node(function, "patterns");
 
-        // get the sibling nodes that are of kind function
         if verbose {
-            print_nodes(&sig, 0, source_code, false);
+            println!("Patterns nodes: {}", patterns.len());
+            println!(
+                "Patterns: {:?}",
+                patterns
+                    .iter()
+                    .map(|p| &source_code[p.start_byte()..p.end_byte()])
+                    .collect::<Vec<&str>>()
+            );
         }
 
-        let mut sibling_cursor = tree.walk();
-        sibling_cursor.goto_first_child();
-        sibling_cursor.goto_first_child();
-        while sibling_cursor.node() != sig {
-            sibling_cursor.goto_next_sibling();
-        }
+        // the last (first because of traverse reversing) pattern is what we want
+        let pattern = &patterns[patterns.len() - 1];
+        let mut cursor = pattern.walk();
+        let mut args = pattern
+            .named_children(&mut cursor)
+            .map(|p| parse_pattern(p, source_code))
+            .collect::<Vec<Pattern>>();
+        assert!(
+            args.len() == adt.args.len(),
+            "Clause of {func_name} has {} patterns, expected {}",
+            args.len(),
+            adt.args.len()
+        );
 
-        let mut functions: Vec<tree_sitter::Node> = Vec::new();
-        if sibling_cursor.goto_next_sibling() {
-            loop {
-                let sibling = sibling_cursor.node();
-                if sibling.kind() == "function" {
-                    functions.push(sibling);
-                } else {
-                    break;
-                }
-                if !sibling_cursor.goto_next_sibling() {
-                    break;
-                }
+        // a clause on a single value keeps its constructor pattern in con, or None for a catch-all
+        let con = if adt.is_single_value() {
+            match args.pop() {
+                Some(Pattern::Con(con)) => Ok(con),
+                Some(Pattern::Var(name)) => Err(name),
+                None => unreachable!("checked above"),
             }
-        }
+        } else {
+            Ok(FuncInput {
+                prefix: func_name.to_string(),
+                input: args.iter().map(ToString::to_string).collect(),
+            })
+        };
+        // the bodies of the clause, several when it has guards
+        let mut cursor = function.walk();
+        let matches = function
+            .children(&mut cursor)
+            .filter(|n| n.kind() == "match")
+            .collect::<Vec<Node<'_>>>();
+
         if verbose {
-            println!("Function nodes: {}", functions.len());
+            println!("Operations nodes: {}", matches.len());
             println!(
-                "Function sources: {:?}",
-                functions
+                "Operations source: {:?}",
+                matches
                     .iter()
-                    .map(|f| &source_code[f.start_byte()..f.end_byte()])
+                    .map(|o| &source_code[o.start_byte()..o.end_byte()])
                     .collect::<Vec<&str>>()
             );
         }
-        let mut input_cons: Vec<FuncInput> = Vec::new();
-        for function in functions {
-            let patterns = traverse_and_capture_from_node(function, "patterns");
-
-            if verbose {
-                println!("Patterns nodes: {}", patterns.len());
-                println!(
-                    "Patterns: {:?}",
-                    patterns
-                        .iter()
-                        .map(|p| &source_code[p.start_byte()..p.end_byte()])
-                        .collect::<Vec<&str>>()
-                );
-            }
 
-            // the last (first because of traverse reversing) pattern is what we want
-            let pattern = &patterns[patterns.len() - 1];
+        let operation = parse_guards(
+            &matches,
+            source_code,
+            func_name,
+            returns,
+            &mut failures,
+            verbose,
+        );
 
-            let constructors = traverse_and_capture_from_node(*pattern, "constructor");
+        let mut binds = Vec::new();
 
-            if verbose {
-                println!("Constructor nodes: {}", constructors.len());
-                println!(
-                    "Constructors: {:?}",
-                    constructors
-                        .iter()
-                        .map(|c| &source_code[c.start_byte()..c.end_byte()])
-                        .collect::<Vec<&str>>()
-                );
-            }
+        let local_binds = traverse_and_capture_from_node(function, "local_binds");
+        if !local_binds.is_empty() {
             assert!(
-                (constructors.len() == 1),
-                "Expected exactly one constructor in the pattern"
+                (local_binds.len() == 1),
+                "Expected exactly one local_binds node"
             );
-            let constr_prefix =
-                &source_code[constructors[0].start_byte()..constructors[0].end_byte()];
+            binds = parse_local_binds(source_code, verbose, local_binds[0]);
+        }
 
-            let mut input_strs = Vec::new();
-            let inputs = traverse_and_capture_from_node(*pattern, "variable");
-            for input in &inputs {
-                if verbose {
-                    println!(
-                        "Input: {}",
-                        &source_code[input.start_byte()..input.end_byte()]
-                    );
-                }
-                let input_str = &source_code[input.start_byte()..input.end_byte()];
-                input_strs.push(input_str);
+        // a catch-all stands for a clause on each constructor the clauses above it don't match
+        let cons = match &con {
+            Ok(con) => vec![con.clone()],
+            Err(name) => {
+                let bodies = binds.iter().map(|b| &b.opp).collect::<Vec<&Operation>>();
+                reject_whole_value(func_name, name, &[&[&operation], &bodies[..]].concat());
+                adt.unmatched(&funcs)
             }
-
-            input_strs.reverse(); // because they are captured in reverse order
-
-            let con = FuncInput {
-                prefix: constr_prefix.to_string(),
-                input: input_strs.iter().map(|s| (*s).to_string()).collect(),
+        };
+        for con in cons {
+            let func = Func {
+                name: func_name.to_string(),
+                con,
+                opp: operation.clone(),
+                local_binds: binds.clone(),
+                args: args.clone(),
             };
-            input_cons.push(con.clone());
-            let operations = traverse_and_capture_from_node(function, "match");
-
-            if verbose {
-                println!("Operations nodes: {}", operations.len());
-                println!("Operations: {operations:?}");
-                println!(
-                    "Operations source: {:?}",
-                    operations
-                        .iter()
-                        .map(|o| &source_code[o.start_byte()..o.end_byte()])
-                        .collect::<Vec<&str>>()
-                );
-            }
-
-            // we only care for the last match (first because of traverse reversing)
-            let mut child_cursor = operations[operations.len() - 1].walk();
-            child_cursor.goto_first_child();
-            child_cursor.goto_next_sibling(); // skip "match"
-
-            let operation = parse_operation(&mut child_cursor, source_code, verbose);
-
-            let mut binds = Vec::new();
+            funcs.push(func);
+        }
+        // the clauses below a catch-all can't match
+        if con.is_err() {
+            break;
+        }
+    }
+    assert!(!funcs.is_empty(), "No clauses found for {func_name}");
+    if let Returns::Outcome(Some(error)) = returns {
+        check_error(func_name, error, &failures);
+    }
+    if verbose {
+        println!("All functions: {funcs:?}");
+    }
+    funcs
+}
 
-            let local_binds = traverse_and_capture_from_node(function, "local_binds");
-            if !local_binds.is_empty() {
+// folds the guarded bodies of a clause into one, each guard applying when the ones above it fail
+fn parse_guards(
+    matches: &[Node<'_>],
+    source_code: &str,
+    func_name: &str,
+    returns: &Returns<'_>,
+    failures: &mut Vec<String>,
+    verbose: bool,
+) -> Operation {
+    let mut operation = None;
+    for node in matches.iter().rev() {
+        let body = node
+            .named_child(node.named_child_count() - 1)
+            .expect("Expected an expression after =");
+        let body = parse_body(body, source_code, returns, failures, verbose);
+        let guard = node
+            .children(&mut node.walk())
+            .find(|n| n.kind() == "guards")
+            .map(|guards| {
                 assert!(
-                    (local_binds.len() == 1),
-                    "Expected exactly one local_binds node"
+                    guards.named_child_count() == 1,
+                    "Only single guards are supported in {func_name}"
                 );
-                binds = parse_local_binds(source_code, verbose, local_binds[0]);
+                let mut cursor = guards.named_child(0).expect("Expected a guard").walk();
+                cursor.goto_first_child();
+                parse_operation(&mut cursor, source_code, verbose)
+            });
+        operation = Some(match (guard, operation) {
+            (None | Some(Operation::BoolLit(true)), _) => body,
+            (Some(Operation::Var(v)), _) if v == "otherwise" => body,
+            (Some(_), _) if matches!(returns, Returns::Value(Type::Int)) => {
+                panic!("Guards are only supported in predicates, {func_name} returns an Int")
             }
+            (Some(guard), Some(rest)) => Operation::branch(guard, body, rest),
+            (Some(_), None) => panic!("The guards of {func_name} must end with otherwise"),
+        });
+    }
+    operation.expect("Expected a body in the clause")
+}
 
-            let func = Func {
-                con: con.clone(),
-                opp: operation,
-                local_binds: binds,
+// parses the body of a clause, following if expressions down to the values it returns,
+// and adds the errors it fails with to `failures`
+fn parse_body(
+    node: Node<'_>,
+    source_code: &str,
+    returns: &Returns<'_>,
+    failures: &mut Vec<String>,
+    verbose: bool,
+) -> Operation {
+    let text = &source_code[node.start_byte()..node.end_byte()];
+    match (node.kind(), returns) {
+        ("parens", _) => {
+            let inner = node
+                .named_child(0)
+                .expect("Expected an expression inside the parens");
+            parse_body(inner, source_code, returns, failures, verbose)
+        }
+        ("conditional", Returns::Value(Type::Int)) => {
+            panic!("If expressions are only supported in predicates: {text}")
+        }
+        ("conditional", _) => {
+            let part = |i| {
+                node.named_child(i)
+                    .expect("Expected a condition and two branches")
             };
-            funcs.push(func.clone());
+            Operation::branch(
+                parse_operation(&mut part(0).walk(), source_code, verbose),
+                parse_body(part(1), source_code, returns, failures, verbose),
+                parse_body(part(2), source_code, returns, failures, verbose),
+            )
+        }
+        (_, Returns::Value(_)) => parse_operation(&mut node.walk(), source_code, verbose),
+        ("constructor", Returns::Outcome(target)) if text == "Nothing" => {
+            Operation::outcome(None, *target)
         }
+        ("apply", Returns::Outcome(target)) => {
+            let head = node.named_child(0).expect("Expected a constructor");
+            let payload = node.named_child(1).expect("Expected an argument");
+            match &source_code[head.start_byte()..head.end_byte()] {
+                "Right" => Operation::outcome(None, *target),
+                "Left" | "Just" => {
+                    let error = error_name(payload, source_code);
+                    if !failures.iter().any(|f| f == error) {
+                        failures.push(error.to_string());
+                    }
+                    Operation::outcome(Some(error), *target)
+                }
+                _ => panic!("Expected Left, Right, Just or Nothing, found {text}"),
+            }
+        }
+        _ => panic!("Expected Left, Right, Just or Nothing, found {text}"),
     }
-    if verbose {
-        println!("All functions: {funcs:?}");
+}
+
+// the name an error is searched for by, its constructor or the message it carries
+fn error_name<'s>(node: Node<'_>, source_code: &'s str) -> &'s str {
+    let text = &source_code[node.start_byte()..node.end_byte()];
+    match node.kind() {
+        "constructor" => text,
+        "apply" | "parens" => {
+            error_name(node.named_child(0).expect("Expected an error"), source_code)
+        }
+        "literal" | "string" if text.starts_with('"') => text.trim_matches('"'),
+        _ => panic!("Unsupported error {text}, expected a constructor or a string"),
     }
-    funcs
 }
 
 fn parse_operation(cursor: &mut TreeCursor<'_>, source_code: &str, verbose: bool) -> Operation {
@@ -263,40 +522,24 @@ fn parse_operation(cursor: &mut TreeCursor<'_>, source_code: &str, verbose: bool
 
     match child.kind() {
         "infix" => {
-            let mut left = None;
-            let mut right = None;
-            let mut op = None;
-            if cursor.goto_first_child() {
-                left = Some(parse_operation(&mut cursor.clone(), source_code, verbose));
-                cursor.goto_next_sibling();
-                op = Some(&source_code[cursor.node().start_byte()..cursor.node().end_byte()]);
-                cursor.goto_next_sibling();
-                right = Some(parse_operation(&mut cursor.clone(), source_code, verbose));
-            }
-
-            if let (Some(left_op), Some(right_op), Some(operator)) = (left, right, op) {
-                match operator {
-                    ">" => Operation::Gt(Box::new(left_op), Box::new(right_op)),
-                    "<" => Operation::Lt(Box::new(left_op), Box::new(right_op)),
-                    "==" => Operation::Eq(Box::new(left_op), Box::new(right_op)),
-                    "/=" => Operation::Neq(Box::new(left_op), Box::new(right_op)),
-                    "<=" => Operation::Leq(Box::new(left_op), Box::new(right_op)),
-                    ">=" => Operation::Geq(Box::new(left_op), Box::new(right_op)),
-                    "+" => Operation::Add(Box::new(left_op), Box::new(right_op)),
-                    "-" => Operation::Sub(Box::new(left_op), Box::new(right_op)),
-                    "*" => Operation::Mul(Box::new(left_op), Box::new(right_op)),
-                    "&&" => Operation::And(Box::new(left_op), Box::new(right_op)),
-                    "||" => Operation::Or(Box::new(left_op), Box::new(right_op)),
-                    _ => panic!("Unknown operator: {operator}"),
-                }
-            } else {
-                panic!("Incomplete infix operation");
-            }
+            // tree-sitter doesn't know the fixities of the operators, so flatten the chain of
+            // them and rebuild it with the fixities from the Prelude
+            let mut operands = Vec::new();
+            let mut operators = Vec::new();
+            flatten_infix(child, source_code, verbose, &mut operands, &mut operators);
+            reassociate(&operands, &operators)
         }
 
         "literal" => {
             let val = &source_code[child.start_byte()..child.end_byte()];
-            Operation::IntLit(val.parse::<i32>().unwrap())
+            Operation::IntLit(
+                val.parse()
+                    .unwrap_or_else(|_| panic!("Unsupported literal: {val}")),
+            )
+        }
+        "negation" => {
+            let operand = child.named_child(0).expect("Expected an operand after -");
+            negate(operand, source_code, verbose)
         }
         "variable" | "name" => {
             let name = &source_code[child.start_byte()..child.end_byte()];
@@ -331,10 +574,16 @@ fn parse_operation(cursor: &mut TreeCursor<'_>, source_code: &str, verbose: bool
 
         "apply" => {
             cursor.goto_first_child(); // go to function
-            let func = parse_operation(cursor, source_code, verbose);
+            let func = parse_operation(&mut cursor.clone(), source_code, verbose);
             cursor.goto_next_sibling(); // go to argument
-            let arg = parse_operation(cursor, source_code, verbose);
-            Operation::Apply(Box::new(func), Box::new(arg))
+            if func == Operation::Var("negate".to_string()) {
+                return negate(cursor.node(), source_code, verbose);
+            }
+            let arg = parse_operation(&mut cursor.clone(), source_code, verbose);
+            match func {
+                Operation::Var(name) if name == "not" => Operation::Not(Box::new(arg)),
+                _ => Operation::Apply(Box::new(func), Box::new(arg)),
+            }
         }
 
         _ => panic!(
@@ -345,6 +594,91 @@ fn parse_operation(cursor: &mut TreeCursor<'_>, source_code: &str, verbose: bool
     }
 }
 
+// `-x` or `negate x`, a literal negated in place so the most negative Int fits
+fn negate(operand: Node<'_>, source_code: &str, verbose: bool) -> Operation {
+    let text = &source_code[operand.start_byte()..operand.end_byte()];
+    if operand.kind() == "literal" {
+        return Operation::IntLit(
+            format!("-{text}")
+                .parse()
+                .unwrap_or_else(|_| panic!("Unsupported literal: -{text}")),
+        );
+    }
+    Operation::Sub(
+        Box::new(Operation::IntLit(0)),
+        Box::new(parse_operation(&mut operand.walk(), source_code, verbose)),
+    )
+}
+
+fn flatten_infix<'s>(
+    node: Node<'_>,
+    source_code: &'s str,
+    verbose: bool,
+    operands: &mut Vec<Operation>,
+    operators: &mut Vec<&'s str>,
+) {
+    let mut cursor = node.walk();
+    for (i, part) in node.children(&mut cursor).enumerate() {
+        if i == 1 {
+            operators.push(&source_code[part.start_byte()..part.end_byte()]);
+        } else if part.kind() == "infix" {
+            flatten_infix(part, source_code, verbose, operands, operators);
+        } else {
+            operands.push(parse_operation(&mut part.walk(), source_code, verbose));
+        }
+    }
+}
+
+// precedence and whether the operator is right associative, as declared in the Prelude and QuickCheck
+fn fixity(operator: &str) -> (u8, bool) {
+    match operator {
+        "*" => (7, false),
+        "+" | "-" => (6, false),
+        ">" | "<" | "==" | "/=" | "<=" | ">=" => (4, false),
+        "&&" => (3, true),
+        "||" => (2, true),
+        "==>" => (0, true),
+        _ => panic!("Unknown operator: {operator}"),
+    }
+}
+
+// splits the chain at the operator that binds loosest, the last one of them unless they associate to the right
+pub fn reassociate(operands: &[Operation], operators: &[&str]) -> Operation {
+    let Some(loosest) = operators.iter().map(|op| fixity(op).0).min() else {
+        return operands[0].clone();
+    };
+    let mut splits = operators
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| fixity(op).0 == loosest)
+        .peekable();
+    let right_associative = splits.peek().is_some_and(|(_, op)| fixity(op).1);
+    let (split, operator) = if right_associative {
+        splits.next()
+    } else {
+        splits.last()
+    }
+    .expect("the loosest operator is in the chain");
+
+    let left = Box::new(reassociate(&operands[..=split], &operators[..split]));
+    let right = Box::new(reassociate(&operands[split + 1..], &operators[split + 1..]));
+    match *operator {
+        ">" => Operation::Gt(left, right),
+        "<" => Operation::Lt(left, right),
+        "==" => Operation::Eq(left, right),
+        "/=" => Operation::Neq(left, right),
+        "<=" => Operation::Leq(left, right),
+        ">=" => Operation::Geq(left, right),
+        "+" => Operation::Add(left, right),
+        "-" => Operation::Sub(left, right),
+        "*" => Operation::Mul(left, right),
+        "&&" => Operation::And(left, right),
+        "||" => Operation::Or(left, right),
+        "==>" => Operation::Implies(left, right),
+        _ => panic!("Unknown operator: {operator}"),
+    }
+}
+
 fn parse_local_binds(source_code: &str, verbose: bool, bind: Node<'_>) -> Vec<Func> {
     let mut functions = Vec::new();
 
@@ -366,46 +700,27 @@ fn parse_local_binds(source_code: &str, verbose: bool, bind: Node<'_>) -> Vec<Fu
             (cursor.node().kind() == "patterns"),
             "Expected patterns node in local bind function"
         );
-        cursor.goto_first_child();
 
-        let variables = traverse_and_capture_from_node(cursor.node(), "variable");
-        let literals = traverse_and_capture_from_node(cursor.node(), "literal");
+        let mut args = Vec::new();
+        cursor.goto_first_child();
+        loop {
+            if cursor.node().is_named() {
+                args.push(parse_pattern(cursor.node(), source_code));
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
 
         if verbose {
             println!(
-                "Local bind function variables: {:?}",
-                variables
-                    .iter()
-                    .map(|v| &source_code[v.start_byte()..v.end_byte()])
-                    .collect::<Vec<&str>>()
-            );
-            println!(
-                "Local bind function literals: {:?}",
-                literals
-                    .iter()
-                    .map(|l| &source_code[l.start_byte()..l.end_byte()])
-                    .collect::<Vec<&str>>()
+                "Local bind function patterns: {:?}",
+                args.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>()
             );
         }
 
-        // currently only handling variables and literals as inputs, not adts
-
-        let mut inputs = Vec::new(); // Placeholder for inputs
-
-        if !variables.is_empty() {
-            for var in variables {
-                let var_name = &source_code[var.start_byte()..var.end_byte()];
-                inputs.push(var_name.to_string());
-            }
-        }
-
-        if !literals.is_empty() {
-            for lit in literals {
-                let lit_value = &source_code[lit.start_byte()..lit.end_byte()];
-                inputs.push(lit_value.to_string());
-            }
-        }
-
         cursor.goto_parent(); // back to patterns
         cursor.goto_next_sibling(); // go to body
 
@@ -418,15 +733,115 @@ fn parse_local_binds(source_code: &str, verbose: bool, bind: Node<'_>) -> Vec<Fu
         let operation = parse_operation(cursor, source_code, verbose);
 
         let func = Func {
+            name: func_name.to_string(),
             con: FuncInput {
                 prefix: func_name.to_string(),
-                input: inputs,
+                input: args.iter().map(ToString::to_string).collect(),
             },
             opp: operation,
             local_binds: Vec::new(),
+            args,
         };
         functions.push(func);
     }
 
+    // captured in reverse order, but clause order matters for pattern matching
+    functions.reverse();
     functions
 }
+
+// parses a single argument pattern of a local bind, eg `c`, `0`, `Leaf` or `(Node h l r)`
+fn parse_pattern(node: Node<'_>, source_code: &str) -> Pattern {
+    let text = &source_code[node.start_byte()..node.end_byte()];
+    if let Some(literal) = literal_pattern(node, source_code) {
+        return Pattern::Var(literal);
+    }
+    match node.kind() {
+        "variable" | "wildcard" => Pattern::Var(text.to_string()),
+        "constructor" => Pattern::Con(FuncInput {
+            prefix: text.to_string(),
+            input: Vec::new(),
+        }),
+        "parens" => {
+            let inner = node
+                .named_child(0)
+                .expect("Expected a pattern inside the parens");
+            parse_pattern(inner, source_code)
+        }
+        "apply" => {
+            // constructor applications nest to the left, eg ((Node h) l) r
+            let mut spine = node;
+            let mut input = Vec::new();
+            while spine.kind() == "apply" {
+                let arg = spine.child(1).expect("Expected an argument in the pattern");
+                match parse_pattern(arg, source_code) {
+                    Pattern::Var(name) => input.push(name),
+                    Pattern::Con(_) => {
+                        panic!("Nested constructor patterns are not supported: {text}")
+                    }
+                }
+                spine = spine
+                    .child(0)
+                    .expect("Expected a constructor in the pattern");
+            }
+            input.reverse();
+            match parse_pattern(spine, source_code) {
+                Pattern::Con(con) => Pattern::Con(FuncInput {
+                    prefix: con.prefix,
+                    input,
+                }),
+                Pattern::Var(_) => panic!("Expected a constructor at the head of pattern {text}"),
+            }
+        }
+        _ => panic!("Unsupported pattern kind {}: {text}", node.kind()),
+    }
+}
+
+// a literal sub-pattern of a constructor pattern, eg `0`, `(-1)` or `True`, as haskell writes it
+fn literal_pattern(node: Node<'_>, source_code: &str) -> Option<String> {
+    let text = &source_code[node.start_byte()..node.end_byte()];
+    match node.kind() {
+        "literal" if node.child(0).is_some_and(|n| n.kind() == "integer") => Some(text.to_string()),
+        "negation" if node.named_child(0).is_some_and(|n| n.kind() == "integer") => {
+            Some(format!("({text})"))
+        }
+        "constructor" if matches!(text, "True" | "False") => Some(text.to_string()),
+        "parens" => literal_pattern(node.named_child(0)?, source_code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::parser::parse;
+
+    #[test]
+    fn reads_negation_and_negative_literals() {
+        let source = "data Shape = Circle Int | Empty
+
+p :: Shape -> Bool
+p (Circle r) = -r > negate 3 && r < -2147483648 + 5
+p Empty = negate (1 * 2) < 0
+";
+        let (_, funcs) = parse(source, "hs", None, None, false);
+        assert_eq!(
+            funcs
+                .iter()
+                .map(|f| f.opp.to_string())
+                .collect::<Vec<String>>(),
+            vec!["(0 - r > -3 /\\ r < -2147483648 + 5)", "0 - 1 * 2 < 0"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported literal: 3000000000")]
+    fn rejects_literals_out_of_range() {
+        let source = "data Shape = Circle Int | Empty
+
+p :: Shape -> Bool
+p (Circle r) = r < 3000000000
+p Empty = True
+";
+        let _ = parse(source, "hs", None, None, false);
+    }
+}
@@ -1,62 +1,621 @@
-use tree_sitter::Tree;
+use tree_sitter::{Node, Tree};
 
-use crate::adt::{Adt, Cons};
-use crate::parse::parser_utils::{print_node, print_nodes, traverse_and_capture_from_node};
+use crate::adt::{Adt, Cons, Func, FuncInput, Operation, Pattern, Type};
+use crate::parse::haskell_parser::reassociate;
+use crate::parse::parser_utils::{
+    print_nodes, reject_whole_value, traverse_and_capture, traverse_and_capture_from_node,
+};
+
+/* the idris grammar grew out of the haskell one, but the kinds of its expression and pattern nodes
+differ between versions (eg `exp_apply` against `apply`). declarations are found by their kind,
+data, signature and function, and below them the walk goes by the shape of the tree: operators,
+parens and literals are told apart by their text rather than their kind.
+
+the frontend covers less than the haskell one: a predicate takes a single value of the data type
+and returns a Bool, with no Int-valued, multi-argument or Either predicates, and its clauses are
+plain equations, with no guards, with clauses, or if, case, let and lambda expressions. these
+are rejected by name rather than misread.
+*/
+
+const OPERATORS: [&str; 11] = ["*", "+", "-", ">", "<", "==", "/=", "<=", ">=", "&&", "||"];
+
+// the keywords of the expressions the frontend doesn't support
+const UNSUPPORTED: [&str; 5] = ["if", "case", "let", "\\", "with"];
 
 pub fn collect_idris_adts(tree: &Tree, source_code: &str, verbose: bool) -> Adt {
-    let root = tree.root_node();
+    if verbose {
+        print_nodes(&tree.root_node(), 0, source_code, false);
+    }
 
-    let adt_nodes = traverse_and_capture_from_node(root, "data");
-    // let mut adts = Vec::new();
+    // the data keyword is a node of the same kind as the declaration it starts
+    let adt_nodes = traverse_and_capture(tree, "data")
+        .into_iter()
+        .filter(Node::is_named)
+        .collect::<Vec<Node<'_>>>();
     assert!(
-        (adt_nodes.len() == 2),
+        (adt_nodes.len() == 1),
         "Expected exactly one ADT in the source file, found {}",
         adt_nodes.len()
     );
+    let tokens = leaves(adt_nodes[0], source_code);
 
-    let adt_node = &adt_nodes[0];
-
+    let name = match tokens.get(1) {
+        Some(name) if is_constructor(name) => (*name).to_string(),
+        other => panic!("Expected a type name after data, found {other:?}"),
+    };
     if verbose {
-        print_nodes(adt_node, 0, source_code, false);
+        println!("ADT Name: {name}");
     }
 
-    let name = {
-        let binding = traverse_and_capture_from_node(*adt_node, "data_name");
-        let adt_name_node = binding.first().expect("Could not find ADT name node");
-
-        source_code[adt_name_node.start_byte()..adt_name_node.end_byte()].to_string()
+    let constructors = match tokens.get(2).copied() {
+        // data Test = IntC Int | BoolC Bool
+        Some("=") => tokens[3..]
+            .split(|t| *t == "|")
+            .map(|alt| {
+                let prefix = match alt.first() {
+                    Some(prefix) if is_constructor(prefix) => (*prefix).to_string(),
+                    other => panic!("Expected a constructor name, found {other:?}"),
+                };
+                let types = alt[1..].iter().map(|t| parse_type(t, &name)).collect();
+                Cons { prefix, types }
+            })
+            .collect::<Vec<Cons>>(),
+        // data Test : Type where
+        //   IntC : Int -> Test
+        Some(":") => {
+            let body = tokens
+                .iter()
+                .position(|t| *t == "where")
+                .map(|i| &tokens[i + 1..])
+                .expect("Expected where after the type of the data declaration");
+            // each constructor starts with its name and a colon
+            let starts = (0..body.len())
+                .filter(|&i| is_constructor(body[i]) && body.get(i + 1) == Some(&":"))
+                .chain([body.len()])
+                .collect::<Vec<usize>>();
+            starts
+                .windows(2)
+                .map(|w| parse_typed_constructor(&body[w[0]..w[1]], &name))
+                .collect::<Vec<Cons>>()
+        }
+        other => panic!("Expected = or : after the type name, found {other:?}"),
     };
 
+    let adt = Adt {
+        args: vec![Type::Custom(name.clone())],
+        output: Type::Bool,
+        name,
+        constructors,
+    };
     if verbose {
-        println!("ADT Name: {name}");
+        println!("Adt: {adt:?}");
+    }
+    adt
+}
+
+/// collects the clauses of the predicate, the first `Adt -> Bool` function in the file unless it is named
+pub fn collect_idris_functions(
+    tree: &Tree,
+    source_code: &str,
+    adt: &Adt,
+    predicate: Option<&str>,
+    verbose: bool,
+) -> Vec<Func> {
+    let mut skipped = Vec::new();
+    // captured in reverse order, but the first predicate in the file is the default
+    for sig in traverse_and_capture(tree, "signature").into_iter().rev() {
+        if !is_top_level(sig) {
+            continue;
+        }
+        // signatures look like foo : Test -> Bool
+        let text = node_text(sig, source_code);
+        let Some((func_name, ty)) = text.split_once(':') else {
+            continue;
+        };
+        // after any visibility modifier, eg export
+        let func_name = func_name.split_whitespace().last().unwrap_or_default();
+        if predicate.is_some_and(|p| p != func_name) {
+            continue;
+        }
+
+        let types = ty.split("->").map(str::trim).collect::<Vec<&str>>();
+        // skip anything that isn't a predicate on the Adt, eg main : IO ()
+        if types != [adt.name.as_str(), "Bool"] {
+            assert!(
+                predicate.is_none(),
+                "Expected {func_name} to be a predicate {} -> Bool, predicates over several \
+                arguments or returning an Int or an Either are not supported for idris",
+                adt.name
+            );
+            if verbose {
+                println!(
+                    "Skipping {func_name}, it is not a predicate on {}",
+                    adt.name
+                );
+            }
+            // main and the like aren't worth naming, the ones that mention the Adt are
+            if types.contains(&adt.name.as_str()) {
+                skipped.push(text.trim());
+            }
+            continue;
+        }
+
+        return collect_clauses(tree, source_code, adt, func_name, verbose);
     }
 
-    let cursor = &mut adt_node.walk();
-    let mut constructors: Vec<Cons> = Vec::new();
-    for child in adt_node.children(&mut cursor.clone()) {
-        if child.kind() == "exp_name" {
-            // process constructor
-            print_node(&child, source_code);
-            let prefix_name = &source_code[child.start_byte()..child.end_byte()];
+    assert!(
+        skipped.is_empty(),
+        "No predicate {} -> Bool in the input, predicates over several arguments or returning an \
+        Int or an Either are not supported for idris: {}",
+        adt.name,
+        skipped.join(", ")
+    );
+    panic!(
+        "No predicate {} : {} -> Bool in the input",
+        predicate.unwrap_or("of type"),
+        adt.name
+    )
+}
 
-            while cursor.goto_next_sibling() {}
-            constructors.push(Cons {
-                prefix: prefix_name.to_owned(),
-                types: Vec::new(),
+fn collect_clauses(
+    tree: &Tree,
+    source_code: &str,
+    adt: &Adt,
+    func_name: &str,
+    verbose: bool,
+) -> Vec<Func> {
+    let mut funcs: Vec<Func> = Vec::new();
+
+    // local binds are functions too, the clauses are the ones outside any other
+    for function in traverse_and_capture(tree, "function").into_iter().rev() {
+        if !is_top_level(function) || within(function, "signature") {
+            continue;
+        }
+        let (lhs, body) = clause_parts(function, source_code);
+        if node_text(lhs[0], source_code) != func_name {
+            continue;
+        }
+        assert!(
+            lhs.len() == 2,
+            "Expected {func_name} to take one argument, but its clause {} takes {}",
+            node_text(function, source_code),
+            lhs.len() - 1
+        );
+
+        // a clause keeps its constructor pattern in con, or the variable of a catch-all
+        let con = match parse_pattern(lhs[1], source_code) {
+            Pattern::Con(con) => Ok(con),
+            Pattern::Var(name) => Err(name),
+        };
+        let operation = parse_operation(body, source_code);
+
+        let mut binds = traverse_and_capture_from_node(function, "function")
+            .into_iter()
+            .filter(|f| f.id() != function.id() && !within(*f, "signature"))
+            .map(|f| parse_local_bind(f, source_code))
+            .collect::<Vec<Func>>();
+        // captured in reverse order, but clause order matters for pattern matching
+        binds.reverse();
+
+        // a catch-all stands for a clause on each constructor the clauses above it don't match
+        let cons = match &con {
+            Ok(con) => vec![con.clone()],
+            Err(name) => {
+                let bodies = binds.iter().map(|b| &b.opp).collect::<Vec<&Operation>>();
+                reject_whole_value(func_name, name, &[&[&operation], &bodies[..]].concat());
+                adt.unmatched(&funcs)
+            }
+        };
+        for con in cons {
+            funcs.push(Func {
+                name: func_name.to_string(),
+                con,
+                opp: operation.clone(),
+                local_binds: binds.clone(),
+                args: Vec::new(),
             });
+        }
+        // the clauses below a catch-all can't match
+        if con.is_err() {
+            break;
+        }
+    }
+
+    assert!(!funcs.is_empty(), "No clauses found for {func_name}");
+    if verbose {
+        println!("All functions: {funcs:?}");
+    }
+    funcs
+}
+
+// a clause of a local bind, eg `f c (Node h l r) = h <= c`
+fn parse_local_bind(function: Node<'_>, source_code: &str) -> Func {
+    let (lhs, body) = clause_parts(function, source_code);
+    let name = node_text(lhs[0], source_code).to_string();
+    let args = lhs[1..]
+        .iter()
+        .map(|p| parse_pattern(*p, source_code))
+        .collect::<Vec<Pattern>>();
+    Func {
+        name: name.clone(),
+        con: FuncInput {
+            prefix: name,
+            input: args.iter().map(ToString::to_string).collect(),
+        },
+        opp: parse_operation(body, source_code),
+        local_binds: Vec::new(),
+        args,
+    }
+}
+
+// the function name and argument patterns of a clause, and the expression after its =
+fn clause_parts<'t>(function: Node<'t>, source_code: &str) -> (Vec<Node<'t>>, Node<'t>) {
+    let mut parts = Vec::new();
+    unwrap_sides(function, &mut parts);
 
-            panic!("Not yet implemented");
+    let eq = parts
+        .iter()
+        .position(|n| node_text(*n, source_code) == "=")
+        .unwrap_or_else(|| {
+            panic!(
+                "Guards and with clauses are not supported for idris: {}",
+                node_text(function, source_code)
+            )
+        });
+    let mut lhs = parts[..eq]
+        .iter()
+        .copied()
+        .filter(Node::is_named)
+        .collect::<Vec<Node<'t>>>();
+    // or the name and patterns as one application
+    if let [whole] = lhs[..] {
+        let mut cursor = whole.walk();
+        let named = whole.named_children(&mut cursor).collect::<Vec<Node<'t>>>();
+        if named.len() > 1 {
+            lhs = named;
         }
     }
+    assert!(!lhs.is_empty(), "Expected a function name before =");
+    let body = parts[eq + 1..]
+        .iter()
+        .copied()
+        .find(Node::is_named)
+        .expect("Expected an expression after =");
+    (lhs, body)
+}
 
-    panic!("Not yet implemented");
+// some versions wrap the two sides of the = in nodes of their own
+fn unwrap_sides<'t>(node: Node<'t>, parts: &mut Vec<Node<'t>>) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match child.kind() {
+            "lhs" | "funvar" | "patterns" | "rhs" | "match" => unwrap_sides(child, parts),
+            _ => parts.push(child),
+        }
+    }
 }
 
-pub fn collect_idris_functions(
-    _tree: &Tree,
-    _source_code: &str,
-    _name: &str,
-    _verbose: bool,
-) -> Vec<crate::adt::Func> {
-    panic!("Not yet implemented");
+// a pattern is flat, so its tokens are enough, eg `c`, `0`, `Leaf` or `(Node h l r)`
+fn parse_pattern(node: Node<'_>, source_code: &str) -> Pattern {
+    let text = node_text(node, source_code);
+    let tokens = leaves(node, source_code);
+    let tokens = match tokens.as_slice() {
+        ["(", inner @ .., ")"] if !inner.is_empty() => inner,
+        tokens => tokens,
+    };
+    match tokens {
+        ["-", n] if n.parse::<i32>().is_ok() => Pattern::Var(format!("(-{n})")),
+        [single] if !is_constructor(single) || is_bool(single) => {
+            Pattern::Var((*single).to_string())
+        }
+        [prefix, fields @ ..] if is_constructor(prefix) => {
+            let mut input = Vec::new();
+            let mut rest = fields;
+            while let Some((field, tail)) = rest.split_first() {
+                match (*field, tail) {
+                    // a negative literal is written in parens, eg (-1)
+                    ("(", ["-", n, ")", tail @ ..]) => {
+                        input.push(format!("(-{n})"));
+                        rest = tail;
+                    }
+                    (field, tail) if !is_constructor(field) || is_bool(field) => {
+                        input.push(field.to_string());
+                        rest = tail;
+                    }
+                    _ => panic!("Nested constructor patterns are not supported: {text}"),
+                }
+            }
+            Pattern::Con(FuncInput {
+                prefix: (*prefix).to_string(),
+                input,
+            })
+        }
+        _ => panic!("Unsupported pattern {text}"),
+    }
+}
+
+fn parse_operation(node: Node<'_>, source_code: &str) -> Operation {
+    let text = node_text(node, source_code);
+    let mut cursor = node.walk();
+    let children = node
+        .children(&mut cursor)
+        .filter(|n| !node_text(*n, source_code).is_empty())
+        .collect::<Vec<Node<'_>>>();
+    let texts = children
+        .iter()
+        .map(|n| node_text(*n, source_code))
+        .collect::<Vec<&str>>();
+
+    match texts.as_slice() {
+        [] => match text {
+            "True" => Operation::BoolLit(true),
+            "False" => Operation::BoolLit(false),
+            _ if text.parse::<i32>().is_ok() => Operation::IntLit(text.parse().unwrap()),
+            _ if !is_constructor(text) => Operation::Var(text.to_string()),
+            _ => panic!("Unknown constructor: {text}"),
+        },
+        // negative literals have to be written in parens, eg (-3)
+        ["-", n] | ["(", "-", n, ")"] => Operation::IntLit(
+            format!("-{n}")
+                .parse()
+                .unwrap_or_else(|_| panic!("Expected a number after -, found {n}")),
+        ),
+        [_] => parse_operation(children[0], source_code),
+        // otherwise read as the application of their parts
+        _ if texts.iter().any(|t| UNSUPPORTED.contains(t)) => {
+            panic!("If, case, let and lambda expressions are not supported for idris: {text}")
+        }
+        ["(", .., ")"] => {
+            assert!(children.len() == 3, "Tuples are not supported: {text}");
+            parse_operation(children[1], source_code)
+        }
+        _ if texts.iter().any(|t| OPERATORS.contains(t)) => {
+            // tree-sitter doesn't know the fixities of the operators, so flatten the chain of
+            // them and rebuild it with the fixities from the Prelude
+            let mut operands = Vec::new();
+            let mut operators = Vec::new();
+            flatten_infix(node, source_code, &mut operands, &mut operators);
+            reassociate(&operands, &operators)
+        }
+        _ => {
+            let mut cursor = node.walk();
+            let mut parts = node
+                .named_children(&mut cursor)
+                .map(|n| parse_operation(n, source_code));
+            let head = parts.next().expect("Expected an expression");
+            parts.fold(head, |func, arg| match func {
+                Operation::Var(name) if name == "not" => Operation::Not(Box::new(arg)),
+                _ => Operation::Apply(Box::new(func), Box::new(arg)),
+            })
+        }
+    }
+}
+
+fn flatten_infix<'s>(
+    node: Node<'_>,
+    source_code: &'s str,
+    operands: &mut Vec<Operation>,
+    operators: &mut Vec<&'s str>,
+) {
+    let mut cursor = node.walk();
+    for part in node.children(&mut cursor) {
+        let text = node_text(part, source_code);
+        if OPERATORS.contains(&text) {
+            operators.push(text);
+        } else if part.kind() == node.kind() {
+            flatten_infix(part, source_code, operands, operators);
+        } else if !text.is_empty() {
+            operands.push(parse_operation(part, source_code));
+        }
+    }
+}
+
+fn parse_type(name: &str, adt_name: &str) -> Type {
+    match name {
+        "Int" | "Integer" => Type::Int,
+        "Bool" => Type::Bool,
+        // if recursive (eg tree)
+        _ if name == adt_name => Type::Custom(name.to_string()),
+        _ => panic!("Unknown type: {name}"),
+    }
+}
+
+// IntC : Int -> Test, arguments may also be named as in IntC : (n : Int) -> Test
+fn parse_typed_constructor(tokens: &[&str], adt_name: &str) -> Cons {
+    let prefix = tokens[0].to_string();
+    let mut parts = tokens[2..]
+        .split(|t| *t == "->")
+        .map(|part| {
+            let ty = part
+                .iter()
+                .rev()
+                .find(|t| is_constructor(t))
+                .expect("Expected a type in the constructor signature");
+            parse_type(ty, adt_name)
+        })
+        .collect::<Vec<Type>>();
+
+    assert!(
+        parts.pop() == Some(Type::Custom(adt_name.to_string())),
+        "Constructor {prefix} must construct a {adt_name}"
+    );
+    Cons {
+        prefix,
+        types: parts,
+    }
+}
+
+// whether the node is outside any function, ie not in a where block
+fn is_top_level(node: Node<'_>) -> bool {
+    !within(node, "function")
+}
+
+// whether the node is inside one of the kind. the arrow of a function type may be a function node too,
+// so functions within signatures aren't clauses
+fn within(node: Node<'_>, kind: &str) -> bool {
+    let mut parent = node.parent();
+    while let Some(p) = parent {
+        if p.kind() == kind {
+            return true;
+        }
+        parent = p.parent();
+    }
+    false
+}
+
+// the text of the leaves under the node in order, leaving out comments and layout
+fn leaves<'s>(node: Node<'_>, source_code: &'s str) -> Vec<&'s str> {
+    let mut tokens = Vec::new();
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        if node.kind().contains("comment") {
+            continue;
+        }
+        if node.child_count() == 0 {
+            let text = node_text(node, source_code);
+            if !text.is_empty() {
+                tokens.push(text);
+            }
+            continue;
+        }
+        let mut cursor = node.walk();
+        let children = node.children(&mut cursor).collect::<Vec<Node<'_>>>();
+        stack.extend(children.into_iter().rev());
+    }
+    tokens
+}
+
+fn node_text<'s>(node: Node<'_>, source_code: &'s str) -> &'s str {
+    source_code[node.start_byte()..node.end_byte()].trim()
+}
+
+fn is_constructor(name: &str) -> bool {
+    name.starts_with(char::is_uppercase)
+}
+
+fn is_bool(name: &str) -> bool {
+    matches!(name, "True" | "False")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adt::{Pattern, Type};
+    use crate::parse::parser::parse;
+
+    const SHAPES: &str = "module Main
+
+data Shape = Circle Int | Rect Int Int | Empty
+
+double : Int -> Int
+double x = x * 2
+
+fits : Shape -> Bool
+fits (Circle r) = r + 2 * r > 4 && r < 10
+fits (Rect w h) = small w && not (h == 0)
+  where
+    small : Int -> Bool
+    small 0 = False
+    small n = n < 5
+fits Empty = True
+
+main : IO ()
+main = printLn (fits Empty)
+";
+
+    const TREES: &str = "data Tree : Type where
+  Leaf : Tree
+  Node : (v : Int) -> Tree -> Tree -> Tree
+
+isLeaf : Tree -> Bool
+isLeaf Leaf = True
+isLeaf _ = False
+
+positive : Tree -> Bool
+positive (Node v l r) = v > 0
+positive _ = True
+";
+
+    #[test]
+    fn reads_typed_constructors_and_catch_alls() {
+        let (adt, funcs) = parse(TREES, "idr", None, None, false);
+        assert_eq!(
+            adt.constructors[1].types,
+            vec![
+                Type::Int,
+                Type::Custom("Tree".to_string()),
+                Type::Custom("Tree".to_string())
+            ]
+        );
+        // the first predicate, its catch-all standing for the constructors above it don't match
+        assert_eq!(
+            funcs
+                .iter()
+                .map(|f| (f.name.as_str(), f.con.prefix.as_str()))
+                .collect::<Vec<(&str, &str)>>(),
+            vec![("isLeaf", "Leaf"), ("isLeaf", "Node")]
+        );
+
+        let (_, funcs) = parse(TREES, "idr", Some("positive"), None, false);
+        assert_eq!(
+            funcs
+                .iter()
+                .map(|f| f.con.prefix.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["Node", "Leaf"]
+        );
+    }
+
+    #[test]
+    fn skips_functions_that_are_not_predicates() {
+        let (adt, funcs) = parse(SHAPES, "idr", None, None, false);
+        assert_eq!(adt.name, "Shape");
+        assert_eq!(adt.constructors[1].types, vec![Type::Int, Type::Int]);
+
+        assert!(funcs.iter().all(|f| f.name == "fits"));
+        assert_eq!(
+            funcs
+                .iter()
+                .map(|f| f.con.to_string())
+                .collect::<Vec<String>>(),
+            vec!["(Circle r)", "(Rect w h)", "(Empty )"]
+        );
+        // operators are regrouped by their fixities
+        assert_eq!(funcs[0].opp.to_string(), "(r + 2 * r > 4 /\\ r < 10)");
+        assert_eq!(funcs[1].opp.to_string(), "((small w) /\\ !(h == 0))");
+        assert_eq!(
+            funcs[1]
+                .local_binds
+                .iter()
+                .map(|b| b.args.clone())
+                .collect::<Vec<Vec<Pattern>>>(),
+            vec![
+                vec![Pattern::Var("0".to_string())],
+                vec![Pattern::Var("n".to_string())]
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "If, case, let and lambda expressions are not supported")]
+    fn rejects_if_expressions() {
+        let source = "data Tree = Leaf | Node Int Tree Tree
+
+small : Tree -> Bool
+small Leaf = True
+small (Node v l r) = if v > 0 then small l else False
+";
+        let _ = parse(source, "idr", None, None, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "depth : Tree -> Int")]
+    fn names_the_predicates_it_cannot_read() {
+        let source = "data Tree = Leaf | Node Int Tree Tree
+
+depth : Tree -> Int
+depth Leaf = 0
+depth (Node v l r) = 1 + depth l
+";
+        let _ = parse(source, "idr", None, None, false);
+    }
 }
@@ -1,4 +1,5 @@
 mod haskell_parser;
+#[cfg(feature = "idris")]
 mod idris_parser;
 pub mod parser;
 mod parser_utils;
@@ -1,14 +1,20 @@
 // this file works as a wrapper for the 3 languages, handling input from the main file and forwarding to the expected parser.
 use crate::parse::haskell_parser;
+#[cfg(feature = "idris")]
 use crate::parse::idris_parser;
 use crate::parse::rust_parser;
 
 #[must_use]
+/// parses the Adt and the clauses of the predicate, the first one in the file unless it is named.
+/// predicates returning a result are satisfied by success, or by failing with `error` when given
 /// # Panics
-/// Panics if the file type is not supported, ie not a haskell, rust or idris file.
+/// Panics if the file type is not supported, ie not a haskell, rust or idris file, or an idris one when the
+/// crate is built without the idris feature, or the predicate isn't found.
 pub fn parse(
     source_code: &str,
     file_type: &str,
+    predicate: Option<&str>,
+    error: Option<&str>,
     verbose: bool,
 ) -> (crate::adt::Adt, Vec<crate::adt::Func>) {
     let mut parser = tree_sitter::Parser::new();
@@ -16,7 +22,10 @@ pub fn parse(
     let language = match file_type {
         "hs" => tree_sitter_haskell::LANGUAGE,
         "rs" => tree_sitter_rust::LANGUAGE,
+        #[cfg(feature = "idris")]
         "idr" => tree_sitter_idris::LANGUAGE,
+        #[cfg(not(feature = "idris"))]
+        "idr" => panic!("This build has no idris frontend, build it with the idris feature"),
         _ => panic!("Unsupported file type: {file_type}"),
     };
 
@@ -34,19 +43,58 @@ pub fn parse(
         println!("--- Traversing the tree ---");
     }
 
-    let adt = match file_type {
+    let mut adt = match file_type {
         "hs" => haskell_parser::collect_haskell_adts(&tree, source_code, verbose),
         "rs" => rust_parser::collect_rust_adts(&tree, source_code, verbose),
+        #[cfg(feature = "idris")]
         "idr" => idris_parser::collect_idris_adts(&tree, source_code, verbose),
         _ => panic!("Unsupported file type: {file_type}"),
     };
 
-    let funcs = match file_type {
-        "hs" => haskell_parser::collect_haskell_functions(&tree, source_code, &adt.name, verbose),
-        "rs" => rust_parser::collect_rust_functions(&tree, source_code, &adt.name, verbose),
-        "idr" => idris_parser::collect_idris_functions(&tree, source_code, &adt.name, verbose),
+    let mut funcs = match file_type {
+        "hs" => haskell_parser::collect_haskell_functions(
+            &tree,
+            source_code,
+            &mut adt,
+            predicate,
+            error,
+            verbose,
+        ),
+        "rs" => rust_parser::collect_rust_functions(&tree, source_code, &adt, error, verbose),
+        #[cfg(feature = "idris")]
+        "idr" => {
+            assert!(
+                error.is_none(),
+                "Predicates returning errors are only supported in haskell and rust"
+            );
+            idris_parser::collect_idris_functions(&tree, source_code, &adt, predicate, verbose)
+        }
         _ => panic!("Unsupported file type: {file_type}"),
     };
 
+    // the haskell and idris frontends only collect the predicate asked for, the rust one collects them all
+    if let Some(predicate) = predicate {
+        funcs.retain(|f| f.name == predicate);
+        assert!(!funcs.is_empty(), "No predicate {predicate} in the input");
+    }
+
     (adt, funcs)
 }
+
+/// checks the error searched for is one the predicate fails with, by its constructor or message,
+/// as a misspelt one would otherwise just never be found
+/// # Panics
+/// Panics listing the errors the predicate fails with when `error` isn't among them.
+pub(crate) fn check_error(func_name: &str, error: &str, failures: &[String]) {
+    let mut failures = failures.to_vec();
+    failures.sort();
+    assert!(
+        failures.iter().any(|f| f == error),
+        "{func_name} never fails with {error}, {}",
+        if failures.is_empty() {
+            "it doesn't fail at all".to_string()
+        } else {
+            format!("the errors it fails with are {}", failures.join(", "))
+        }
+    );
+}
@@ -1,5 +1,7 @@
 use tree_sitter::Tree;
 
+use crate::adt::Operation;
+
 // traverses the given tree and captures all nodes of the specified kind
 pub fn traverse_and_capture<'a>(tree: &'a Tree, kind: &'a str) -> Vec<tree_sitter::Node<'a>> {
     let root = tree.root_node();
@@ -34,6 +36,17 @@ pub fn traverse_and_capture_from_node<'a>(
     captured_nodes
 }
 
+// a catch-all stands for a clause on each constructor it matches, and the clauses only see the
+// fields of their constructor, so one whose body uses the whole value can't be expanded
+pub fn reject_whole_value(func_name: &str, name: &str, bodies: &[&Operation]) {
+    if bodies.iter().any(|body| body.mentions(name)) {
+        panic!(
+            "{func_name} uses {name}, the whole value its catch-all matches, but the clauses can only \
+            use the fields of a constructor: match the constructors {name} stands for instead"
+        );
+    }
+}
+
 pub fn print_node(node: &tree_sitter::Node, text: &str) {
     println!(
         "Node: {} [{}-{}] \n text: '{}' \n\n",
@@ -1,16 +1,31 @@
-use tree_sitter::Tree;
+use tree_sitter::{Node, Tree};
 
 use crate::adt::{Adt, Cons, Func, FuncInput, Operation, Type};
-use crate::parse::parser_utils::{print_nodes, traverse_and_capture_from_node};
+use crate::parse::parser::check_error;
+use crate::parse::parser_utils::{print_nodes, reject_whole_value, traverse_and_capture_from_node};
 
 pub fn collect_rust_adts(tree: &Tree, source_code: &str, verbose: bool) -> Adt {
     let root = tree.root_node();
 
-    let adt_nodes = traverse_and_capture_from_node(root, "enum_item");
-    // let mut adts = Vec::new();
+    // the enums that only appear as the errors of a predicate returning a Result or an Option
+    let error_types = traverse_and_capture_from_node(root, "function_item")
+        .into_iter()
+        .filter_map(|f| f.child_by_field_name("return_type"))
+        .filter(|result| is_outcome(*result, source_code))
+        .flat_map(|result| traverse_and_capture_from_node(result, "type_identifier"))
+        .map(|n| &source_code[n.start_byte()..n.end_byte()])
+        .collect::<Vec<&str>>();
+
+    let adt_nodes = traverse_and_capture_from_node(root, "enum_item")
+        .into_iter()
+        .filter(|node| {
+            node.child_by_field_name("name")
+                .is_none_or(|n| !error_types.contains(&&source_code[n.start_byte()..n.end_byte()]))
+        })
+        .collect::<Vec<Node<'_>>>();
     assert!(
         (adt_nodes.len() == 1),
-        "Expected exactly one ADT in the source file, found {}",
+        "Expected exactly one ADT besides the error types in the source file, found {}",
         adt_nodes.len()
     );
 
@@ -28,7 +43,9 @@ pub fn collect_rust_adts(tree: &Tree, source_code: &str, verbose: bool) -> Adt {
         println!("ADT Name: {adt_name}");
     }
 
-    let constructor_nodes = traverse_and_capture_from_node(*adt_node, "enum_variant");
+    // captured in reverse order, but tags follow the declaration
+    let mut constructor_nodes = traverse_and_capture_from_node(*adt_node, "enum_variant");
+    constructor_nodes.reverse();
 
     let mut constructors: Vec<Cons> = Vec::new();
 
@@ -46,7 +63,8 @@ pub fn collect_rust_adts(tree: &Tree, source_code: &str, verbose: bool) -> Adt {
             println!("Constructor Name: {constructor_name}");
         }
 
-        let type_nodes = traverse_and_capture_from_node(constructor_node, "primitive_type");
+        let mut type_nodes = traverse_and_capture_from_node(constructor_node, "primitive_type");
+        type_nodes.reverse();
 
         let mut types: Vec<Type> = Vec::new();
         for type_node in type_nodes {
@@ -71,15 +89,22 @@ pub fn collect_rust_adts(tree: &Tree, source_code: &str, verbose: bool) -> Adt {
         println!("Constructors: {constructors:?}");
     }
     Adt {
+        args: vec![Type::Custom(adt_name.clone())],
+        output: Type::Bool,
         name: adt_name,
         constructors,
     }
 }
 
+/// collects the arms of the match in the predicate. predicates returning `Result<(), E>` or
+/// `Option<E>` hold on `Ok` and `None`, or when given an error, on the `Err` or `Some` of that error
+/// # Panics
+/// Panics if there isn't exactly one function, or its arms aren't supported.
 pub fn collect_rust_functions(
     tree: &Tree,
     source_code: &str,
-    _adt_name: &str,
+    adt: &Adt,
+    error: Option<&str>,
     verbose: bool,
 ) -> Vec<Func> {
     let functions = traverse_and_capture_from_node(tree.root_node(), "function_item");
@@ -95,46 +120,56 @@ pub fn collect_rust_functions(
         print_nodes(function_node, 0, source_code, false);
     }
 
+    let name_node = function_node
+        .child_by_field_name("name")
+        .expect("Could not find function name node");
+    let func_name = source_code[name_node.start_byte()..name_node.end_byte()].to_string();
+
+    let outcome = function_node
+        .child_by_field_name("return_type")
+        .is_some_and(|result| is_outcome(result, source_code));
+    assert!(
+        outcome || error.is_none(),
+        "{func_name} doesn't return a Result or an Option, it can't fail with {}",
+        error.unwrap_or_default()
+    );
+
     let mut funcs = Vec::new();
+    // the errors the arms fail with, to check the one searched for against
+    let mut failures = Vec::new();
     //currently we only support one function per file with a match statement inside it
-    let func_nodes = traverse_and_capture_from_node(*function_node, "match_arm");
+    // the arms are captured in reverse order, but a catch-all only matches what the ones above don't
+    let mut func_nodes = traverse_and_capture_from_node(*function_node, "match_arm");
+    func_nodes.reverse();
+
+    // the guarded arms on each constructor waiting for the arm they fall through to, with the
+    // names of its fields and the guards and bodies over the fields numbered, eg `#0`
+    let mut guarded: Vec<(FuncInput, Vec<(Operation, Operation)>)> = Vec::new();
 
     for func_node in func_nodes {
         // parse each match arm into a Func
         let mut cursor = func_node.walk();
         cursor.goto_first_child();
         let pattern_node = cursor.node();
-        let mut identifiers = traverse_and_capture_from_node(pattern_node, "identifier");
-        identifiers
-            .pop()
-            .expect("empty identifiers in function pattern");
 
-        let constructor_name_node = identifiers
-            .pop()
-            .expect("Could not find function constructor name node");
-
-        let constructor_name = source_code
-            [constructor_name_node.start_byte()..constructor_name_node.end_byte()]
-            .to_string();
-        if verbose {
-            println!("Function Constructor: {constructor_name}");
-        }
-
-        let mut inputs = Vec::new();
-
-        while let Some(input_node) = identifiers.pop() {
-            let input_name =
-                source_code[input_node.start_byte()..input_node.end_byte()].to_string();
-            inputs.push(input_name);
-        }
-        inputs.reverse();
-        if verbose {
-            println!("Function Inputs: {inputs:?}");
-        }
-
-        let func_input = FuncInput {
-            prefix: constructor_name,
-            input: inputs,
+        // an arm on `_` or a variable stands for one on each constructor the arms above don't match,
+        // but a bare constructor name is a unit variant brought in scope, eg by `use Tree::*`.
+        // the pattern comes first, before any guard
+        let catch_all = match pattern_node.child(0) {
+            Some(n) if n.kind() == "_" => Some("_"),
+            Some(n) if n.kind() == "identifier" => {
+                let name = &source_code[n.start_byte()..n.end_byte()];
+                (!adt.constructors.iter().any(|c| c.prefix == name)).then_some(name)
+            }
+            _ => None,
+        };
+        let cons = if let Some(name) = catch_all {
+            if verbose {
+                println!("Function Catch-all: {name}");
+            }
+            adt.unmatched(&funcs)
+        } else {
+            vec![arm_pattern(pattern_node, source_code, verbose)]
         };
 
         let mut cursor = func_node.walk();
@@ -156,70 +191,453 @@ pub fn collect_rust_functions(
             println!("Function Expression: {expr_text}");
         }
 
-        match expr_node.kind() {
-            "identifier" => {
-                let value = source_code[expr_node.start_byte()..expr_node.end_byte()].to_string();
-                if verbose {
-                    println!("Function is ConstSelf with value: {value}");
+        let opp = parse_body(
+            expr_node,
+            source_code,
+            outcome.then_some(error),
+            &mut failures,
+            verbose,
+        );
+        if let Some(name) = catch_all {
+            reject_whole_value(&func_name, name, &[&opp]);
+        }
+
+        // an arm with a guard, eg `Shape::Circle(r) if r > 0 => ..`, falls through to the arms
+        // below when it fails, so it waits for the next arm matching all of its constructor.
+        // a guarded catch-all does so on each constructor it stands for
+        if let Some(condition) = pattern_node.child_by_field_name("condition") {
+            let text = &source_code[pattern_node.start_byte()..pattern_node.end_byte()];
+            let guard = parse_expression(condition, source_code, verbose);
+            if let Some(name) = catch_all {
+                reject_whole_value(&func_name, name, &[&guard]);
+            }
+            for con in &cons {
+                assert!(
+                    con.literals().is_empty(),
+                    "Guards are only supported on arms matching a constructor with variable fields: {text}"
+                );
+                let arm = (number_fields(&guard, con), number_fields(&opp, con));
+                match guarded.iter_mut().find(|(c, _)| c.prefix == con.prefix) {
+                    Some((names, arms)) => {
+                        name_fields(names, con);
+                        arms.push(arm);
+                    }
+                    None => guarded.push((con.clone(), vec![arm])),
                 }
-                // create Func with ConstSelf
-                let func = Func {
-                    con: func_input,
-                    opp: Operation::Var(value),
-                    local_binds: Vec::new(),
-                };
-                funcs.push(func);
             }
-            "binary_expression" => {
-                let mut bin_cursor = expr_node.walk();
-                bin_cursor.goto_first_child();
-                let left_node = bin_cursor.node();
-                bin_cursor.goto_next_sibling();
-                let operator_node = bin_cursor.node();
-                bin_cursor.goto_next_sibling();
-                let right_node = bin_cursor.node();
-                let left_value =
-                    source_code[left_node.start_byte()..left_node.end_byte()].to_string();
-                let right_value =
-                    source_code[right_node.start_byte()..right_node.end_byte()].to_string();
-                let operator_value =
-                    source_code[operator_node.start_byte()..operator_node.end_byte()].to_string();
-                if verbose {
-                    println!(
-                        "Left: {left_value}, Operator: {operator_value}, Right: {right_value}"
+            continue;
+        }
+
+        for con in cons {
+            let (con, opp) = match guarded.iter().position(|(c, _)| c.prefix == con.prefix) {
+                Some(i) => {
+                    assert!(
+                        con.literals().is_empty(),
+                        "The arms of {func_name} after a guarded one on {} must match all of its values",
+                        con.prefix
                     );
+                    let (mut names, arms) = guarded.remove(i);
+                    name_fields(&mut names, &con);
+                    // each guard applies when the ones above it fail
+                    let opp = arms
+                        .into_iter()
+                        .rev()
+                        .fold(number_fields(&opp, &con), |rest, (guard, body)| {
+                            Operation::branch(guard, body, rest)
+                        });
+                    let opp = names
+                        .input
+                        .iter()
+                        .enumerate()
+                        .fold(opp, |o, (j, name)| o.renamed(&format!("#{j}"), name));
+                    (names, opp)
                 }
-                let left_operand = Operation::Var(left_value);
-
-                let right_operand = if let Ok(n) = right_value.parse::<i32>() {
-                    Operation::IntLit(n)
-                } else {
-                    Operation::Var(right_value)
-                };
-
-                let operation = match operator_value.as_str() {
-                    "+" => Operation::Add(Box::new(left_operand), Box::new(right_operand)),
-                    ">" => Operation::Gt(Box::new(left_operand), Box::new(right_operand)),
-                    "<" => Operation::Lt(Box::new(left_operand), Box::new(right_operand)),
-                    "==" => Operation::Eq(Box::new(left_operand), Box::new(right_operand)),
-                    "!=" => Operation::Neq(Box::new(left_operand), Box::new(right_operand)),
-                    ">=" => Operation::Geq(Box::new(left_operand), Box::new(right_operand)),
-                    "<=" => Operation::Leq(Box::new(left_operand), Box::new(right_operand)),
-                    _ => panic!("Unsupported operator: {operator_value}"),
-                };
-
-                let func = Func {
-                    con: func_input,
-                    opp: operation,
-                    local_binds: Vec::new(),
-                };
-                funcs.push(func);
+                None => (con, opp.clone()),
+            };
+            let func = Func {
+                name: func_name.clone(),
+                con,
+                opp,
+                local_binds: Vec::new(),
+                args: Vec::new(),
+            };
+            funcs.push(func);
+        }
+        // the arms below a catch-all can't match
+        if catch_all.is_some() {
+            break;
+        }
+    }
+    assert!(
+        guarded.is_empty(),
+        "The guarded arms of {func_name} need an arm below them to fall through to"
+    );
+
+    if let Some(error) = error {
+        check_error(&func_name, error, &failures);
+    }
+    funcs
+}
+
+// the operation over the fields of the pattern numbered by position, eg `#1` for `b` in `Span(a, b)`,
+// so arms naming them differently can be joined
+fn number_fields(opp: &Operation, con: &FuncInput) -> Operation {
+    con.input
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| *name != "_")
+        .fold(opp.clone(), |o, (j, name)| {
+            o.renamed(name, &format!("#{j}"))
+        })
+}
+
+// names the fields `names` leaves as `_` after the pattern of another arm on the constructor
+fn name_fields(names: &mut FuncInput, con: &FuncInput) {
+    for (j, other) in con.input.iter().enumerate() {
+        if names.input[j] == "_" && other != "_" {
+            assert!(
+                !names.input.contains(other),
+                "The arms on {} give different fields the name {other}",
+                con.prefix
+            );
+            names.input[j].clone_from(other);
+        }
+    }
+}
+
+// the constructor an arm matches and the patterns of its fields, eg `Range::Span(a, b)`. the fields
+// may be `_` or literals, kept as haskell writes them, eg `(-1)` for `-1`
+fn arm_pattern(pattern_node: Node<'_>, source_code: &str, verbose: bool) -> FuncInput {
+    let pattern = pattern_node
+        .named_child(0)
+        .expect("Could not find the pattern of the arm");
+    let text = |n: Node<'_>| source_code[n.start_byte()..n.end_byte()].to_string();
+
+    // a constructor without fields is just its path, eg `Shape::Empty`
+    let (path, fields) = if pattern.kind() == "tuple_struct_pattern" {
+        let path = pattern
+            .child_by_field_name("type")
+            .expect("Could not find the constructor of the pattern");
+        let mut cursor = pattern.walk();
+        let fields = pattern
+            .children(&mut cursor)
+            .skip(1)
+            .filter(|n| !matches!(n.kind(), "(" | ")" | ","))
+            .collect::<Vec<Node<'_>>>();
+        (path, fields)
+    } else {
+        (pattern, Vec::new())
+    };
+    let constructor_name = match path.child_by_field_name("name") {
+        Some(name) => text(name),
+        None => text(path),
+    };
+    if verbose {
+        println!("Function Constructor: {constructor_name}");
+    }
+
+    let inputs = fields
+        .into_iter()
+        .map(|field| match field.kind() {
+            "identifier" | "_" | "integer_literal" => text(field),
+            "negative_literal" => format!("({})", text(field)),
+            "boolean_literal" if text(field) == "true" => "True".to_string(),
+            "boolean_literal" => "False".to_string(),
+            kind => panic!("Unsupported pattern {} of kind {kind}", text(field)),
+        })
+        .collect::<Vec<String>>();
+    if verbose {
+        println!("Function Inputs: {inputs:?}");
+    }
+
+    FuncInput {
+        prefix: constructor_name,
+        input: inputs,
+    }
+}
+
+// whether the type is a Result or an Option, whose Ok or None means success
+fn is_outcome(ty: Node<'_>, source_code: &str) -> bool {
+    ty.kind() == "generic_type"
+        && ty.child_by_field_name("type").is_some_and(|head| {
+            matches!(
+                &source_code[head.start_byte()..head.end_byte()],
+                "Result" | "Option"
+            )
+        })
+}
+
+// parses the body of an arm, following blocks and if expressions down to the values it returns.
+// `outcome` holds the error searched for when the predicate returns a Result or an Option,
+// and the errors the arm fails with are added to `failures`
+fn parse_body(
+    node: Node<'_>,
+    source_code: &str,
+    outcome: Option<Option<&str>>,
+    failures: &mut Vec<String>,
+    verbose: bool,
+) -> Operation {
+    let text = &source_code[node.start_byte()..node.end_byte()];
+    match (node.kind(), outcome) {
+        ("block" | "expression_statement" | "parenthesized_expression", _) => {
+            assert!(
+                node.named_child_count() == 1,
+                "Only blocks of a single expression are supported: {text}"
+            );
+            let inner = node.named_child(0).expect("Expected an expression");
+            parse_body(inner, source_code, outcome, failures, verbose)
+        }
+        ("if_expression", _) => {
+            let part = |field| {
+                node.child_by_field_name(field)
+                    .unwrap_or_else(|| panic!("Expected an if with an else: {text}"))
+            };
+            let alternative = part("alternative")
+                .named_child(0)
+                .expect("Expected an expression after else");
+            Operation::branch(
+                parse_expression(part("condition"), source_code, verbose),
+                parse_body(part("consequence"), source_code, outcome, failures, verbose),
+                parse_body(alternative, source_code, outcome, failures, verbose),
+            )
+        }
+        (_, None) => parse_expression(node, source_code, verbose),
+        ("identifier", Some(target)) if text == "None" => Operation::outcome(None, target),
+        ("call_expression", Some(target)) => {
+            let function = node
+                .child_by_field_name("function")
+                .expect("Expected a function in the call");
+            let payload = node
+                .child_by_field_name("arguments")
+                .and_then(|args| args.named_child(0))
+                .unwrap_or_else(|| panic!("Expected an argument in {text}"));
+            match &source_code[function.start_byte()..function.end_byte()] {
+                "Ok" => Operation::outcome(None, target),
+                "Err" | "Some" => {
+                    let error = error_name(payload, source_code);
+                    if !failures.iter().any(|f| f == error) {
+                        failures.push(error.to_string());
+                    }
+                    Operation::outcome(Some(error), target)
+                }
+                _ => panic!("Expected Ok, Err, Some or None, found {text}"),
+            }
+        }
+        _ => panic!("Expected Ok, Err, Some or None, found {text}"),
+    }
+}
+
+// the name an error is searched for by, its variant or the message it carries
+fn error_name<'s>(node: Node<'_>, source_code: &'s str) -> &'s str {
+    let text = &source_code[node.start_byte()..node.end_byte()];
+    match node.kind() {
+        "identifier" => text,
+        "scoped_identifier" => {
+            let name = node
+                .child_by_field_name("name")
+                .expect("Expected a variant name");
+            &source_code[name.start_byte()..name.end_byte()]
+        }
+        // a variant with fields, or a message converted with to_string or into
+        "call_expression" => error_name(
+            node.child_by_field_name("function")
+                .expect("Expected a function in the call"),
+            source_code,
+        ),
+        "field_expression" => error_name(
+            node.child_by_field_name("value")
+                .expect("Expected a value before the method"),
+            source_code,
+        ),
+        "string_literal" => text.trim_matches('"'),
+        _ => panic!("Unsupported error {text}, expected a variant or a string"),
+    }
+}
+
+// translates a rust expression into an Operation, recursing into its subexpressions
+fn parse_expression(node: Node<'_>, source_code: &str, verbose: bool) -> Operation {
+    let text = &source_code[node.start_byte()..node.end_byte()];
+    if verbose {
+        println!("Parsing expression node: {} '{text}'", node.kind());
+    }
+
+    match node.kind() {
+        "identifier" => Operation::Var(text.to_string()),
+        "boolean_literal" => Operation::BoolLit(text == "true"),
+        "integer_literal" => Operation::IntLit(
+            text.trim_end_matches("i32")
+                .replace('_', "")
+                .parse()
+                .unwrap_or_else(|_| panic!("Unsupported integer literal: {text}")),
+        ),
+        "parenthesized_expression" => {
+            let inner = node
+                .named_child(0)
+                .expect("Expected an expression inside the parens");
+            parse_expression(inner, source_code, verbose)
+        }
+        "unary_expression" => {
+            let operator = node.child(0).expect("Expected a unary operator");
+            let operand = node.child(1).expect("Expected an operand");
+            let operator = &source_code[operator.start_byte()..operator.end_byte()];
+            match (operator, parse_expression(operand, source_code, verbose)) {
+                ("!", op) => Operation::Not(Box::new(op)),
+                ("-", Operation::IntLit(i)) => Operation::IntLit(-i),
+                ("-", op) => Operation::Sub(Box::new(Operation::IntLit(0)), Box::new(op)),
+                _ => panic!("Unsupported unary operator: {operator}"),
             }
-            _ => {
-                panic!("Unsupported function expression kind: {}", expr_node.kind());
+        }
+        "binary_expression" => {
+            let left = node
+                .child_by_field_name("left")
+                .expect("Expected a left operand");
+            let right = node
+                .child_by_field_name("right")
+                .expect("Expected a right operand");
+            let operator = node
+                .child_by_field_name("operator")
+                .expect("Expected an operator");
+            let operator = &source_code[operator.start_byte()..operator.end_byte()];
+
+            let left = Box::new(parse_expression(left, source_code, verbose));
+            let right = Box::new(parse_expression(right, source_code, verbose));
+            match operator {
+                "+" => Operation::Add(left, right),
+                "-" => Operation::Sub(left, right),
+                "*" => Operation::Mul(left, right),
+                ">" => Operation::Gt(left, right),
+                "<" => Operation::Lt(left, right),
+                "==" => Operation::Eq(left, right),
+                "!=" => Operation::Neq(left, right),
+                ">=" => Operation::Geq(left, right),
+                "<=" => Operation::Leq(left, right),
+                "&&" => Operation::And(left, right),
+                "||" => Operation::Or(left, right),
+                _ => panic!("Unsupported operator: {operator}"),
             }
         }
+        _ => panic!("Unsupported function expression kind: {}", node.kind()),
     }
+}
 
-    funcs
+#[cfg(test)]
+mod tests {
+    use crate::parse::parser::parse;
+
+    const SHAPES: &str = "enum Shape {
+    Circle(i32),
+    Rect(i32, i32),
+    Empty,
+}
+
+use Shape::*;
+
+fn p(s: Shape) -> bool {
+    match s {
+        Empty => false,
+        Circle(x) => x > 0,
+        _ => false,
+    }
+}
+";
+
+    const GUARDED: &str = "enum Shape {
+    Circle(i32),
+    Rect(i32, i32),
+    Empty,
+}
+
+use Shape::*;
+
+fn p(s: Shape) -> bool {
+    match s {
+        Circle(r) if r > 5 => false,
+        Rect(_, h) if h > 3 => h < 2,
+        Rect(w, _) if w < 0 => true,
+        Empty => false,
+        Circle(x) => x > 0,
+        _ => false,
+    }
+}
+";
+
+    #[test]
+    fn unit_variants_in_scope_are_not_catch_alls() {
+        let (_, funcs) = parse(SHAPES, "rs", None, None, false);
+        assert_eq!(
+            funcs
+                .iter()
+                .map(|f| f.con.to_string())
+                .collect::<Vec<String>>(),
+            vec!["(Empty )", "(Circle x)", "(Rect _ _)"]
+        );
+    }
+
+    #[test]
+    fn guarded_arms_fall_through_to_the_arms_below() {
+        let (_, funcs) = parse(GUARDED, "rs", None, None, false);
+        assert_eq!(
+            funcs
+                .iter()
+                .map(|f| f.con.to_string())
+                .collect::<Vec<String>>(),
+            vec!["(Empty )", "(Circle r)", "(Rect w h)"]
+        );
+        // the fields keep the names of the first arm on the constructor
+        assert_eq!(funcs[1].opp.to_string(), "(!(r > 5) /\\ r > 0)");
+        // and a catch-all is what the last guard falls through to
+        assert_eq!(
+            funcs[2].opp.to_string(),
+            "((h > 3 /\\ h < 2) \\/ (!(h > 3) /\\ w < 0))"
+        );
+    }
+
+    #[test]
+    fn guarded_catch_alls_fall_through_on_each_constructor() {
+        let source = "enum Shape {
+    Circle(i32),
+    Rect(i32, i32),
+    Empty,
+}
+
+fn p(s: Shape) -> bool {
+    match s {
+        Shape::Empty => true,
+        _ if 2 > 1 => false,
+        Shape::Circle(r) => r > 0,
+        _ => true,
+    }
+}
+";
+        let (_, funcs) = parse(source, "rs", None, None, false);
+        assert_eq!(
+            funcs
+                .iter()
+                .map(|f| format!("{} {}", f.con, f.opp))
+                .collect::<Vec<String>>(),
+            vec![
+                "(Empty ) true",
+                "(Circle r) (!(2 > 1) /\\ r > 0)",
+                "(Rect _ _) !(2 > 1)"
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "p uses other, the whole value its catch-all matches")]
+    fn catch_alls_using_the_whole_value_are_rejected() {
+        let source = "enum Shape {
+    Circle(i32),
+    Empty,
+}
+
+fn p(s: Shape) -> bool {
+    match s {
+        Shape::Empty => true,
+        other => !other,
+    }
+}
+";
+        let _ = parse(source, "rs", None, None, false);
+    }
 }
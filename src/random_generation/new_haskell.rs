@@ -18,8 +18,9 @@ struct TeraFunc {
     opp: String,
 }
 
-pub fn generate_haskell_random(max_depth: u32, verbose: bool) {
-    let mut rng = rand::rng();
+/// writes a random haskell predicate to output.hs, the same one every time for a given seed
+pub fn generate_haskell_random(max_depth: u32, seed: u64, verbose: bool) {
+    let mut rng = StdRng::seed_from_u64(seed);
 
     let adt = generate_adt(&mut rng, verbose, max_depth);
 
@@ -70,16 +71,21 @@ fn generate_adt(rng: &mut impl Rng, verbose: bool, max_depth: u32) -> Adt {
         let prefix = format!("Constructor{}", i + 1);
         let num_types = rng.random_range(1..max_depth);
         let mut types = Vec::new();
+        // the first constructor is never recursive so that the Adt has finite values
+        let kinds = if i == 0 { 2 } else { 3 };
         for _ in 0..num_types {
-            types.push(match rng.random_range(0..2) {
+            types.push(match rng.random_range(0..kinds) {
                 0 => crate::adt::Type::Int,
                 1 => crate::adt::Type::Bool,
+                2 => crate::adt::Type::Custom(random_name.clone()),
                 _ => panic!("Unexpected random value"),
             });
         }
         constructors.push(crate::adt::Cons { prefix, types });
     }
     let adt = Adt {
+        args: vec![crate::adt::Type::Custom(random_name.clone())],
+        output: crate::adt::Type::Bool,
         name: random_name,
         constructors,
     };
@@ -128,9 +134,11 @@ fn generate_func(rng: &mut impl Rng, adt: &Adt, max_depth: u32, verbose: bool) -
         );
 
         let func = crate::adt::Func {
+            name: "predicate".to_string(),
             con,
             opp,
             local_binds: Vec::new(),
+            args: Vec::new(),
         };
         result.push(func);
     }
@@ -153,20 +161,27 @@ fn generate_operation(
         let mut candidates = Vec::new();
         for (i, t) in constructor.types.iter().enumerate() {
             if *t == return_type {
-                candidates.push(input.input[i].clone());
+                candidates.push(Operation::Var(input.input[i].clone()));
+            }
+            // recursive fields are checked with a recursive call to the predicate
+            if matches!(t, crate::adt::Type::Custom(_)) && return_type == crate::adt::Type::Bool {
+                candidates.push(Operation::Apply(
+                    Box::new(Operation::Var("predicate".to_string())),
+                    Box::new(Operation::Var(input.input[i].clone())),
+                ));
             }
         }
 
         if !candidates.is_empty() && rng.random_bool(0.75) {
             let selected = candidates.choose(rng).unwrap();
 
-            return Operation::Var(selected.to_string());
+            return selected.clone();
         }
 
         return match return_type {
             crate::adt::Type::Bool => Operation::BoolLit(rng.random_bool(0.5)),
             crate::adt::Type::Int => Operation::IntLit(rng.random_range(1..=100)),
-            crate::adt::Type::Custom(_) => todo!(),
+            crate::adt::Type::Custom(_) => unreachable!("operations never return an Adt value"),
         };
     }
 
@@ -384,6 +399,6 @@ fn generate_operation(
             ),
             _ => panic!(),
         },
-        crate::adt::Type::Custom(_) => todo!(),
+        crate::adt::Type::Custom(_) => unreachable!("operations never return an Adt value"),
     }
 }
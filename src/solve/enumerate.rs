@@ -0,0 +1,373 @@
+// A solver that enumerates values of the Adt and evaluates the predicate on them directly,
+// for when conjure isn't installed. Values are built up field by field and the predicate is
+// evaluated on the partial value in three valued logic, so a branch is dropped as soon as the
+// fields assigned so far already make the predicate false.
+use rand::prelude::*;
+
+use crate::adt::{Adt, Bounds, Func, Hash, Query, Type, Value};
+use crate::validate::evaluate::{Evaluator, Term, Val};
+use crate::validate::parse_solution::encode_value;
+
+#[derive(Debug, Clone)]
+enum Partial {
+    Hole,
+    Int(i32),
+    Bool(bool),
+    Con(usize, Vec<Partial>),
+    /// the arguments of a predicate that takes more than a single value
+    Args(Vec<Partial>),
+}
+
+impl Term for Partial {
+    fn view<'a>(&'a self, adt: &'a Adt) -> Val<'a, Self> {
+        match self {
+            Partial::Hole => Val::Unknown,
+            Partial::Int(i) => Val::Int(i64::from(*i)),
+            Partial::Bool(b) => Val::Bool(*b),
+            Partial::Con(con, fields) => Val::Con(&adt.constructors[*con].prefix, fields),
+            Partial::Args(_) => unreachable!("argument lists are spread over the parameters"),
+        }
+    }
+}
+
+struct Search<'f> {
+    adt: &'f Adt,
+    funcs: &'f [Func],
+    min: i32,
+    max: i32,
+    depth: usize,
+    count: usize,
+    negate: bool,
+    rng: Option<StdRng>,
+    hashes: &'f [Hash],
+    visited: usize,
+    found: Vec<Value>,
+}
+
+/// Finds up to `query.count` distinct values answering the query within the bounds by enumeration,
+/// each returned as the assignments conjure would have produced for it.
+#[must_use]
+pub fn solve_enumerate(
+    adt: &Adt,
+    funcs: &[Func],
+    bounds: &Bounds,
+    query: &Query,
+    verbose: bool,
+) -> Vec<Vec<(String, String)>> {
+    let Bounds { min, max, depth } = *bounds;
+    let mut search = Search {
+        adt,
+        funcs,
+        min,
+        max,
+        depth,
+        count: query.count,
+        negate: query.negate,
+        rng: query.seed.map(StdRng::seed_from_u64),
+        hashes: &query.hashes,
+        visited: 0,
+        found: Vec::new(),
+    };
+
+    let value = match query.constructor {
+        // a recursive constructor needs room for its children
+        Some(con) if depth < 2 && adt.constructors[con].recursive_fields() > 0 => {
+            return Vec::new();
+        }
+        Some(con) => Partial::Con(con, vec![Partial::Hole; adt.constructors[con].types.len()]),
+        None => Partial::Hole,
+    };
+    // the path to the value among the arguments
+    let (mut root, value_path) = if adt.is_single_value() {
+        (value, Vec::new())
+    } else {
+        let mut args = vec![Partial::Hole; adt.args.len()];
+        let arg = adt.value_arg();
+        if let Some(arg) = arg {
+            args[arg] = value;
+        }
+        (Partial::Args(args), arg.into_iter().collect())
+    };
+    match query.objective {
+        // fixing the field to each value in turn from the end we want, the first hit is optimal
+        Some(objective) => {
+            assert!(
+                query.constructor.is_some(),
+                "An objective needs the constructor to be fixed"
+            );
+            let values = if objective.maximise {
+                (min..=max).rev().collect::<Vec<i32>>()
+            } else {
+                (min..=max).collect::<Vec<i32>>()
+            };
+            let path = [&value_path[..], &[objective.field]].concat();
+            for v in values {
+                *at_mut(&mut root, &path) = Partial::Int(v);
+                if search.dfs(&mut root) {
+                    break;
+                }
+            }
+        }
+        None => {
+            search.dfs(&mut root);
+        }
+    }
+
+    if verbose {
+        println!("Enumerated {} partial values", search.visited);
+        for value in &search.found {
+            println!("Found value: {value}");
+        }
+    }
+
+    search
+        .found
+        .iter()
+        .map(|value| encode_value(adt, value, min, depth))
+        .collect()
+}
+
+impl<'f> Search<'f> {
+    // returns true once enough values have been found
+    fn dfs(&mut self, root: &mut Partial) -> bool {
+        self.visited += 1;
+        match self.check(root) {
+            // every completion satisfies the predicate, the default one is taken first and the
+            // search carries on into the holes for the others. a randomised search fills the
+            // holes at random instead, and hashes can rule out the default, so they only take
+            // complete values
+            Some(true) if (self.rng.is_none() && self.hashes.is_empty()) || !has_hole(root) => {
+                let value = self.complete(root, &Type::Custom(self.adt.name.clone()));
+                let in_cell = self.hashes.is_empty() || {
+                    let assignments = encode_value(self.adt, &value, self.min, self.depth);
+                    self.hashes.iter().all(|h| h.holds(&assignments))
+                };
+                if in_cell && !self.found.contains(&value) {
+                    self.found.push(value);
+                }
+                if self.found.len() >= self.count || !has_hole(root) {
+                    return self.found.len() >= self.count;
+                }
+            }
+            Some(true) => {}
+            Some(false) => return false,
+            None => {}
+        }
+
+        let Some((path, ty, level)) =
+            self.first_hole(root, &Type::Custom(self.adt.name.clone()), 1)
+        else {
+            // complete but undecided, eg the predicate crashed
+            return false;
+        };
+
+        let mut candidates = self.candidates(&ty, level);
+        if let Some(rng) = &mut self.rng {
+            candidates.shuffle(rng);
+        }
+        for candidate in candidates {
+            *at_mut(root, &path) = candidate;
+            if self.dfs(root) {
+                return true;
+            }
+        }
+        *at_mut(root, &path) = Partial::Hole;
+        false
+    }
+
+    // whether a partial value is a solution, None if that depends on the holes.
+    // a value the predicate crashes on is never a solution, negated or not
+    fn check(&self, root: &Partial) -> Option<bool> {
+        let args = match root {
+            Partial::Args(args) => &args[..],
+            _ => std::slice::from_ref(root),
+        };
+        match Evaluator::new(self.adt, self.funcs).predicate(args) {
+            Ok(Val::Bool(b)) => Some(b != self.negate),
+            Ok(_) | Err(_) if has_hole(root) => None,
+            _ => Some(false),
+        }
+    }
+
+    // the path to, type and level of the first hole in pre-order
+    fn first_hole(
+        &self,
+        partial: &Partial,
+        ty: &Type,
+        level: usize,
+    ) -> Option<(Vec<usize>, Type, usize)> {
+        match partial {
+            Partial::Hole => Some((Vec::new(), ty.clone(), level)),
+            Partial::Con(con, fields) => fields
+                .iter()
+                .zip(&self.adt.constructors[*con].types)
+                .enumerate()
+                .find_map(|(i, (field, t))| {
+                    let level = if matches!(t, Type::Custom(_)) {
+                        level + 1
+                    } else {
+                        level
+                    };
+                    let (mut path, ty, level) = self.first_hole(field, t, level)?;
+                    path.insert(0, i);
+                    Some((path, ty, level))
+                }),
+            // the value among the arguments is the root, on the first level
+            Partial::Args(args) => {
+                args.iter()
+                    .zip(&self.adt.args)
+                    .enumerate()
+                    .find_map(|(i, (arg, t))| {
+                        let (mut path, ty, level) = self.first_hole(arg, t, level)?;
+                        path.insert(0, i);
+                        Some((path, ty, level))
+                    })
+            }
+            _ => None,
+        }
+    }
+
+    fn candidates(&self, ty: &Type, level: usize) -> Vec<Partial> {
+        match ty {
+            Type::Int => (self.min..=self.max).map(Partial::Int).collect(),
+            Type::Bool => vec![Partial::Bool(false), Partial::Bool(true)],
+            Type::Custom(_) => self
+                .adt
+                .constructors
+                .iter()
+                .enumerate()
+                // recursive constructors don't fit at the depth bound
+                .filter(|(_, c)| level < self.depth || c.recursive_fields() == 0)
+                .map(|(i, c)| Partial::Con(i, vec![Partial::Hole; c.types.len()]))
+                .collect(),
+        }
+    }
+
+    // fills the remaining holes, any completion works once the predicate holds
+    fn complete(&self, partial: &Partial, ty: &Type) -> Value {
+        match (partial, ty) {
+            (Partial::Int(i), _) => Value::Int(*i),
+            (Partial::Bool(b), _) => Value::Bool(*b),
+            (Partial::Args(args), _) => Value::Args(
+                args.iter()
+                    .zip(&self.adt.args)
+                    .map(|(a, t)| self.complete(a, t))
+                    .collect(),
+            ),
+            (Partial::Con(con, fields), _) => {
+                let con = &self.adt.constructors[*con];
+                Value::Con(
+                    con.prefix.clone(),
+                    fields
+                        .iter()
+                        .zip(&con.types)
+                        .map(|(f, t)| self.complete(f, t))
+                        .collect(),
+                )
+            }
+            (Partial::Hole, Type::Int) => Value::Int(self.min),
+            (Partial::Hole, Type::Bool) => Value::Bool(false),
+            (Partial::Hole, Type::Custom(_)) => {
+                let (con, leaf) = self
+                    .adt
+                    .constructors
+                    .iter()
+                    .enumerate()
+                    .find(|(_, c)| c.recursive_fields() == 0)
+                    .expect("Expected a non-recursive constructor");
+                self.complete(
+                    &Partial::Con(con, vec![Partial::Hole; leaf.types.len()]),
+                    ty,
+                )
+            }
+        }
+    }
+}
+
+fn has_hole(partial: &Partial) -> bool {
+    match partial {
+        Partial::Hole => true,
+        Partial::Con(_, fields) | Partial::Args(fields) => fields.iter().any(has_hole),
+        _ => false,
+    }
+}
+
+fn at_mut<'p>(partial: &'p mut Partial, path: &[usize]) -> &'p mut Partial {
+    match (partial, path) {
+        (partial, []) => partial,
+        (Partial::Con(_, fields) | Partial::Args(fields), [i, rest @ ..]) => {
+            at_mut(&mut fields[*i], rest)
+        }
+        _ => unreachable!("paths only lead through constructors"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parser::parse;
+
+    const BST: &str = "data Tree = Leaf | Node Int Tree Tree
+
+isBST :: Tree -> Bool
+isBST Leaf = True
+isBST (Node v left right) = (f v left) && (g v right) && (isBST left) && (isBST right)
+   where
+     f c Leaf = True
+     f c (Node h l r) = (h <= c) && (f c l) && (f c r)
+     g c Leaf = True
+     g c (Node h l r) = (h >= c) && (g c l) && (g c r)
+";
+
+    fn search<'f>(adt: &'f Adt, funcs: &'f [Func], negate: bool) -> Search<'f> {
+        Search {
+            adt,
+            funcs,
+            min: -2,
+            max: 2,
+            depth: 3,
+            count: 1,
+            negate,
+            rng: None,
+            hashes: &[],
+            visited: 0,
+            found: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn partial_values_are_decided_once_their_fields_are() {
+        let (adt, funcs) = parse(BST, "hs", None, None, false);
+        let con = |name| adt.constructors.iter().position(|c| c.prefix == name);
+        let leaf = || Partial::Con(con("Leaf").unwrap(), Vec::new());
+        let node = |v, l, r| Partial::Con(con("Node").unwrap(), vec![v, l, r]);
+        let search = search(&adt, &funcs, false);
+
+        // nothing is known yet
+        assert_eq!(search.check(&Partial::Hole), None);
+        // the children still decide it
+        let open = node(Partial::Int(3), Partial::Hole, Partial::Hole);
+        assert_eq!(search.check(&open), None);
+        // a left child above the root fails whatever the holes become
+        let pruned = node(
+            Partial::Int(3),
+            node(Partial::Int(5), Partial::Hole, Partial::Hole),
+            Partial::Hole,
+        );
+        assert_eq!(search.check(&pruned), Some(false));
+        // a value the predicate doesn't look at needn't be known
+        let unread = node(Partial::Hole, leaf(), leaf());
+        assert_eq!(search.check(&unread), Some(true));
+    }
+
+    #[test]
+    fn negation_flips_decided_values_only() {
+        let (adt, funcs) = parse(BST, "hs", None, None, false);
+        let node = adt.constructors.iter().position(|c| c.prefix == "Node");
+        let node = |v, l| Partial::Con(node.unwrap(), vec![v, l, Partial::Hole]);
+        let search = search(&adt, &funcs, true);
+        let pruned = node(Partial::Int(3), node(Partial::Int(5), Partial::Hole));
+        assert_eq!(search.check(&pruned), Some(true));
+        assert_eq!(search.check(&Partial::Hole), None);
+    }
+}
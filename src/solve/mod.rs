@@ -1 +1,4 @@
+pub mod enumerate;
+pub mod sample;
 pub mod solve_conjure;
+pub mod weighted;
@@ -0,0 +1,164 @@
+// Near-uniform sampling in the style of UniGen. Random hash constraints cut the solutions into
+// cells of about the same size, more of them until a cell is small enough to list in full, and
+// each sample is drawn uniformly from the cell a fresh set of hashes picks out. The same cells
+// give an approximate count of the solutions.
+use rand::prelude::*;
+
+use crate::adt::{Adt, Bounds, Hash, Query, Type};
+
+// the most solutions a cell can hold before another hash is added
+const MIN_PIVOT: usize = 16;
+// gives up on a sample after this many cells turn out too big or empty
+const MAX_ATTEMPTS: usize = 64;
+// the approximate count is the median of this many estimates
+const COUNT_TRIALS: usize = 9;
+
+/// Draws `query.count` samples, each uniform over a cell of the solutions, using `solve`
+/// to list the solutions of a query. Samples can repeat, as they are drawn independently.
+pub fn sample_uniform(
+    adt: &Adt,
+    bounds: &Bounds,
+    query: &Query,
+    verbose: bool,
+    mut solve: impl FnMut(&Query) -> Vec<Vec<(String, String)>>,
+) -> Vec<Vec<(String, String)>> {
+    let mut rng = rng(query);
+    let cells = Cells::new(adt, bounds);
+
+    let mut samples = Vec::new();
+    // the number of hashes carries over between samples, it only depends on the solution count
+    let mut hashes = 0;
+    'samples: for _ in 0..query.count {
+        let mut empty = 0;
+        for _ in 0..MAX_ATTEMPTS {
+            let cell = solve(&cells.query(&mut rng, query, hashes));
+
+            if verbose {
+                println!("Cell with {hashes} hashes holds {} solutions", cell.len());
+            }
+
+            if cell.len() > cells.pivot {
+                hashes += 1;
+            } else if cell.is_empty() {
+                if hashes == 0 {
+                    // nothing to sample from at all
+                    break 'samples;
+                }
+                // an empty cell can be bad luck, but not several in a row
+                empty += 1;
+                if empty == 3 {
+                    hashes -= 1;
+                    empty = 0;
+                }
+            } else {
+                samples.push(cell[rng.random_range(0..cell.len())].clone());
+                continue 'samples;
+            }
+        }
+        if verbose {
+            println!("Gave up on a sample after {MAX_ATTEMPTS} cells");
+        }
+    }
+
+    samples
+}
+
+/// Estimates the number of solutions of the query in the style of ApproxMC, as the size of a
+/// small cell times the number of cells, taking the median over several sets of hashes.
+pub fn count_approximate(
+    adt: &Adt,
+    bounds: &Bounds,
+    query: &Query,
+    verbose: bool,
+    mut solve: impl FnMut(&Query) -> Vec<Vec<(String, String)>>,
+) -> u128 {
+    let mut rng = rng(query);
+    let cells = Cells::new(adt, bounds);
+
+    let mut estimates = Vec::new();
+    let mut hashes = 0;
+    for _ in 0..COUNT_TRIALS {
+        let cell = loop {
+            let cell = solve(&cells.query(&mut rng, query, hashes));
+            if cell.len() <= cells.pivot {
+                break cell;
+            }
+            hashes += 1;
+        };
+        let cell_count = u128::try_from(cells.modulus)
+            .expect("the modulus is positive")
+            .saturating_pow(u32::try_from(hashes).expect("overflow"));
+        let estimate = (cell.len() as u128).saturating_mul(cell_count);
+        if verbose {
+            println!(
+                "Cell with {hashes} hashes holds {} solutions, estimate {estimate}",
+                cell.len()
+            );
+        }
+        estimates.push(estimate);
+        // the next trial starts a hash lower, so it doesn't stick to a high guess
+        hashes = hashes.saturating_sub(1);
+    }
+
+    estimates.sort_unstable();
+    estimates[estimates.len() / 2]
+}
+
+fn rng(query: &Query) -> StdRng {
+    match query.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    }
+}
+
+// the hash family over the decision variables of the encoding
+struct Cells {
+    variables: Vec<(String, Type)>,
+    modulus: i64,
+    pivot: usize,
+}
+
+impl Cells {
+    fn new(adt: &Adt, bounds: &Bounds) -> Self {
+        // a prime above every domain size, so distinct values of a variable never hash the same
+        let width = usize::try_from(i64::from(bounds.max) - i64::from(bounds.min) + 1)
+            .expect("overflow")
+            .max(adt.constructors.len());
+        let modulus = (width + 1..)
+            .find(|&n| is_prime(n))
+            .expect("primes are infinite");
+        Cells {
+            variables: adt.variables(bounds.depth),
+            modulus: i64::try_from(modulus).expect("overflow"),
+            // each hash divides the cells by about the modulus, so the window has to be wider than it
+            pivot: MIN_PIVOT.max(2 * modulus),
+        }
+    }
+
+    // the query for the solutions in a random cell cut out by the given number of hashes
+    fn query(&self, rng: &mut StdRng, base: &Query, hashes: usize) -> Query {
+        Query {
+            count: self.pivot + 1,
+            seed: None,
+            hashes: (0..hashes)
+                .map(|_| Hash {
+                    terms: self
+                        .variables
+                        .iter()
+                        .map(|(var, t)| (var.clone(), t.clone(), rng.random_range(0..self.modulus)))
+                        .collect(),
+                    offset: rng.random_range(0..self.modulus),
+                    modulus: self.modulus,
+                })
+                .collect(),
+            ..base.clone()
+        }
+    }
+}
+
+fn is_prime(n: usize) -> bool {
+    n >= 2
+        && (2..)
+            .take_while(|d| d * d <= n)
+            .all(|d| !n.is_multiple_of(d))
+}
@@ -1,12 +1,41 @@
+use std::path::Path;
 use std::process::{Command, Stdio};
 
-/// Solve the given Essence specification using Conjure
+/// Solve the given Essence specification using Conjure, asking for up to `count` solutions.
+/// With a seed Minion searches in a random order, the same one for the same seed.
+/// Returns the paths of the solution files Conjure wrote.
 /// # Panics
 /// Panics if Conjure fails to execute or returns a non-zero status.
-pub fn solve_conjure(essence_file: String, verbose: bool) {
-    let conjure_output = Command::new("conjure")
-        .arg("solve")
-        .arg(essence_file)
+#[must_use]
+pub fn solve_conjure(
+    essence_file: String,
+    count: usize,
+    seed: Option<u64>,
+    verbose: bool,
+) -> Vec<String> {
+    let stem = Path::new(&essence_file)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output")
+        .to_string();
+
+    // solutions left over from an earlier run would be read back as this run's
+    for old in numbered_solutions(&stem) {
+        let _ = std::fs::remove_file(old);
+    }
+    let _ = std::fs::remove_file(format!("{stem}.solution"));
+
+    let mut command = Command::new("conjure");
+    command.arg("solve").arg(&essence_file);
+    if count > 1 {
+        command.arg(format!("--number-of-solutions={count}"));
+    }
+    if let Some(seed) = seed {
+        command.arg(format!(
+            "--solver-options=-randomiseorder -valorder random -randomseed {seed}"
+        ));
+    }
+    let conjure_output = command
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .output()
@@ -28,4 +57,39 @@ pub fn solve_conjure(essence_file: String, verbose: bool) {
         "Conjure failed with status {}",
         conjure_output.status.code().unwrap_or(-1)
     );
+
+    if count > 1 {
+        numbered_solutions(&stem)
+    } else {
+        let single = format!("{stem}.solution");
+        if Path::new(&single).is_file() {
+            vec![single]
+        } else {
+            // conjure writes no solution file when the spec is unsatisfiable
+            Vec::new()
+        }
+    }
+}
+
+// with more than one solution conjure writes <stem>-solution000001.solution and so on
+fn numbered_solutions(stem: &str) -> Vec<String> {
+    let prefix = format!("{stem}-solution");
+    let mut paths = std::fs::read_dir(".")
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|name| name.starts_with(&prefix) && name.ends_with(".solution"))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
+
+/// Whether a conjure binary can be found on the PATH
+#[must_use]
+pub fn conjure_available() -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join("conjure").is_file()))
 }
@@ -0,0 +1,79 @@
+// Constructor weights, like QuickCheck's frequency. Each draw picks the constructor of the value
+// by weight and the values are then solved for with the tag fixed, a constructor that runs out of
+// values hands its remaining draws to the others.
+use rand::prelude::*;
+
+use crate::adt::{Adt, Query};
+
+/// Finds `query.count` values whose constructors are drawn according to `weights`, indexed like
+/// `Adt::constructors`, using `solve` to find the values of one constructor.
+/// Returns fewer when the constructors with a weight run out of values.
+pub fn solve_weighted(
+    adt: &Adt,
+    weights: &[u32],
+    query: &Query,
+    verbose: bool,
+    mut solve: impl FnMut(&Query) -> Vec<Vec<(String, String)>>,
+) -> Vec<Vec<(String, String)>> {
+    let mut rng = match query.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+    let mut weights = weights.to_vec();
+    let mut wanted = vec![0; adt.constructors.len()];
+    let mut found: Vec<Vec<Vec<(String, String)>>> = vec![Vec::new(); adt.constructors.len()];
+
+    let mut draws = query.count;
+    while draws > 0 {
+        let total: u32 = weights.iter().sum();
+        if total == 0 {
+            break;
+        }
+        for _ in 0..draws {
+            let mut pick = rng.random_range(0..total);
+            let con = weights
+                .iter()
+                .position(|w| {
+                    if pick < *w {
+                        true
+                    } else {
+                        pick -= w;
+                        false
+                    }
+                })
+                .expect("pick is below the total weight");
+            wanted[con] += 1;
+        }
+
+        // the solvers are deterministic, so asking again for more gives the earlier values too
+        draws = 0;
+        for (con, values) in found.iter_mut().enumerate() {
+            if wanted[con] == values.len() {
+                continue;
+            }
+            *values = solve(&Query {
+                count: wanted[con],
+                constructor: Some(con),
+                ..query.clone()
+            });
+            if values.len() < wanted[con] {
+                if verbose {
+                    println!(
+                        "{} has only {} values, drawing again for the other {}",
+                        adt.constructors[con].prefix,
+                        values.len(),
+                        wanted[con] - values.len()
+                    );
+                }
+                draws += wanted[con] - values.len();
+                wanted[con] = values.len();
+                weights[con] = 0;
+            }
+        }
+    }
+
+    // in draw order rather than grouped by constructor
+    let mut values = found.concat();
+    values.shuffle(&mut rng);
+    values
+}
@@ -0,0 +1,370 @@
+// evaluates the parsed functions directly on a solution, as an alternative to compiling haskell validation code.
+// the enumerative solver evaluates them with the same evaluator on values it has only partly filled in, in
+// three valued logic, so the result is Unknown when it depends on the fields left open
+use std::collections::HashMap;
+
+use crate::adt::{Adt, Func, Operation, Pattern, Value};
+use crate::validate::parse_solution::decode_value;
+
+// guards against predicates that never reach a base case. each call takes a few KB of the native
+// stack in a debug build, so this stays well within the 2MB a spawned thread gets
+const MAX_CALL_DEPTH: usize = 200;
+
+/// a value the predicate can be evaluated on, which may have parts that aren't known yet
+pub trait Term: Sized + std::fmt::Debug {
+    /// the value as the evaluator sees it, Unknown for a part that isn't known
+    fn view<'a>(&'a self, adt: &'a Adt) -> Val<'a, Self>;
+}
+
+/// the result of evaluating an operation, in three valued logic
+#[derive(Debug)]
+pub enum Val<'a, T> {
+    Int(i64),
+    Bool(bool),
+    /// a constructor and its fields
+    Con(&'a str, &'a [T]),
+    Unknown,
+}
+
+impl<T> Clone for Val<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Val<'_, T> {}
+
+impl Term for Value {
+    fn view<'a>(&'a self, _adt: &'a Adt) -> Val<'a, Self> {
+        match self {
+            Value::Int(i) => Val::Int(i64::from(*i)),
+            Value::Bool(b) => Val::Bool(*b),
+            Value::Con(prefix, fields) => Val::Con(prefix, fields),
+            Value::Args(_) => unreachable!("argument lists are spread over the parameters"),
+        }
+    }
+}
+
+/// whether a value matches a pattern, Unknown when it depends on a part that isn't known
+pub enum Match {
+    Yes,
+    No,
+    Unknown,
+}
+
+/// validates the solution by evaluating the functions on the value it describes,
+/// checking the predicate returns `expected`
+#[must_use]
+pub fn evaluate_validation(
+    adt: &Adt,
+    funcs: &[Func],
+    assignments: &[(String, String)],
+    expected: bool,
+    verbose: bool,
+) -> bool {
+    let value = decode_value(adt, assignments);
+    let result = evaluate(adt, funcs, &value);
+
+    if verbose {
+        println!("Evaluating {} on {value}: {result:?}", funcs[0].name);
+    }
+
+    result == Ok(expected)
+}
+
+/// evaluates the predicate defined by the functions on a value of the Adt, or on its arguments
+/// # Errors
+/// Returns an error if no clause matches, the expression is ill typed or the recursion doesn't terminate.
+pub fn evaluate(adt: &Adt, funcs: &[Func], value: &Value) -> Result<bool, String> {
+    let args = match value {
+        Value::Args(args) => &args[..],
+        _ => std::slice::from_ref(value),
+    };
+    match Evaluator::new(adt, funcs).predicate(args)? {
+        Val::Bool(b) => Ok(b),
+        other => Err(format!(
+            "{} returned {other:?} instead of a Bool",
+            funcs[0].name
+        )),
+    }
+}
+
+pub struct Evaluator<'f> {
+    adt: &'f Adt,
+    funcs: &'f [Func],
+}
+
+impl<'f> Evaluator<'f> {
+    #[must_use]
+    pub fn new(adt: &'f Adt, funcs: &'f [Func]) -> Self {
+        Evaluator { adt, funcs }
+    }
+
+    /// applies the predicate, the first of the functions, to its arguments
+    /// # Errors
+    /// Returns an error if no clause matches, the expression is ill typed or the recursion doesn't terminate.
+    pub fn predicate<'a, T: Term>(&self, args: &'a [T]) -> Result<Val<'a, T>, String>
+    where
+        'f: 'a,
+    {
+        let root = self.funcs.first().ok_or("No functions to evaluate")?;
+        let args = args
+            .iter()
+            .map(|arg| arg.view(self.adt))
+            .collect::<Vec<Val<'a, T>>>();
+        self.call(&root.name, &args, &HashMap::new(), &[], 0)
+    }
+
+    // calls the named function, taking the first clause whose patterns match as haskell does.
+    // Unknown when which clause that is depends on a part that isn't known
+    fn call<'a, T: Term>(
+        &self,
+        name: &str,
+        args: &[Val<'a, T>],
+        env: &HashMap<String, Val<'a, T>>,
+        scope: &'f [Func],
+        depth: usize,
+    ) -> Result<Val<'a, T>, String>
+    where
+        'f: 'a,
+    {
+        if depth > MAX_CALL_DEPTH {
+            return Err(format!("Call to {name} does not terminate"));
+        }
+
+        let local = scope.iter().any(|f| f.name == name);
+        let clauses = if local { scope } else { self.funcs };
+
+        for clause in clauses.iter().filter(|f| f.name == name) {
+            let patterns = if local {
+                clause.args.clone()
+            } else {
+                clause.patterns()
+            };
+            if patterns.len() != args.len() {
+                return Err(format!(
+                    "{name} applied to {} arguments, expected {}",
+                    args.len(),
+                    patterns.len()
+                ));
+            }
+
+            // local binds see the variables of the clause they belong to
+            let mut clause_env = if local { env.clone() } else { HashMap::new() };
+            let mut matched = Match::Yes;
+            for (pattern, arg) in patterns.iter().zip(args) {
+                match self.bind(pattern, *arg, &mut clause_env) {
+                    Match::Yes => {}
+                    Match::No => {
+                        matched = Match::No;
+                        break;
+                    }
+                    Match::Unknown => matched = Match::Unknown,
+                }
+            }
+            match matched {
+                Match::Yes => {}
+                Match::No => continue,
+                Match::Unknown => return Ok(Val::Unknown),
+            }
+
+            let clause_scope = if local {
+                scope
+            } else {
+                &clause.local_binds[..]
+            };
+            return self.eval(&clause.opp, &clause_env, clause_scope, depth + 1);
+        }
+
+        Err(format!("Non-exhaustive patterns in {name}"))
+    }
+
+    // matches a value against a pattern, binding its variables
+    fn bind<'a, T: Term>(
+        &self,
+        pattern: &Pattern,
+        arg: Val<'a, T>,
+        env: &mut HashMap<String, Val<'a, T>>,
+    ) -> Match
+    where
+        'f: 'a,
+    {
+        match (pattern, arg) {
+            (Pattern::Var(name), _) => match (pattern.literal(), arg) {
+                (None, _) => {
+                    env.insert(name.clone(), arg);
+                    Match::Yes
+                }
+                (Some(_), Val::Unknown) => Match::Unknown,
+                (Some(Value::Int(a)), Val::Int(b)) if i64::from(a) == b => Match::Yes,
+                (Some(Value::Bool(a)), Val::Bool(b)) if a == b => Match::Yes,
+                (Some(_), _) => Match::No,
+            },
+            (Pattern::Con(_), Val::Unknown) => Match::Unknown,
+            (Pattern::Con(con), Val::Con(prefix, fields)) => {
+                if con.prefix != prefix {
+                    return Match::No;
+                }
+                // literal sub-patterns can only be checked once their fields are known
+                let mut matched = Match::Yes;
+                for (j, literal) in con.literals() {
+                    match (fields[j].view(self.adt), literal) {
+                        (Val::Unknown, _) => matched = Match::Unknown,
+                        (Val::Int(a), Value::Int(b)) if a == i64::from(b) => {}
+                        (Val::Bool(a), Value::Bool(b)) if a == b => {}
+                        _ => return Match::No,
+                    }
+                }
+                for (name, field) in con.input.iter().zip(fields) {
+                    env.insert(name.clone(), field.view(self.adt));
+                }
+                matched
+            }
+            _ => Match::No,
+        }
+    }
+
+    fn eval<'a, T: Term>(
+        &self,
+        op: &Operation,
+        env: &HashMap<String, Val<'a, T>>,
+        scope: &'f [Func],
+        depth: usize,
+    ) -> Result<Val<'a, T>, String>
+    where
+        'f: 'a,
+    {
+        let eval = |o: &Operation| self.eval(o, env, scope, depth);
+        let int = |o: &Operation| match eval(o)? {
+            Val::Int(i) => Ok(Some(i)),
+            Val::Unknown => Ok(None),
+            other => Err(format!("Expected an Int in {op}, found {other:?}")),
+        };
+        let boolean = |o: &Operation| match eval(o)? {
+            Val::Bool(b) => Ok(Some(b)),
+            Val::Unknown => Ok(None),
+            other => Err(format!("Expected a Bool in {op}, found {other:?}")),
+        };
+        let arith = |l: &Operation, r: &Operation, f: fn(i64, i64) -> i64| {
+            Ok(match (int(l)?, int(r)?) {
+                (Some(a), Some(b)) => Val::Int(f(a, b)),
+                _ => Val::Unknown,
+            })
+        };
+        let compare = |l: &Operation, r: &Operation, f: fn(&i64, &i64) -> bool| {
+            Ok(truth(int(l)?.zip(int(r)?).map(|(a, b)| f(&a, &b))))
+        };
+
+        match op {
+            Operation::BoolLit(b) => Ok(Val::Bool(*b)),
+            Operation::IntLit(i) => Ok(Val::Int(i64::from(*i))),
+            Operation::Var(name) => match env.get(name) {
+                Some(val) => Ok(*val),
+                None => self.call(name, &[], env, scope, depth),
+            },
+            // && and || short circuit like in haskell, and an unknown side doesn't matter when the
+            // other decides the result
+            Operation::And(l, r) => Ok(match boolean(l)? {
+                Some(false) => Val::Bool(false),
+                Some(true) => truth(boolean(r)?),
+                None => match boolean(r)? {
+                    Some(false) => Val::Bool(false),
+                    _ => Val::Unknown,
+                },
+            }),
+            Operation::Or(l, r) => Ok(match boolean(l)? {
+                Some(true) => Val::Bool(true),
+                Some(false) => truth(boolean(r)?),
+                None => match boolean(r)? {
+                    Some(true) => Val::Bool(true),
+                    _ => Val::Unknown,
+                },
+            }),
+            Operation::Implies(l, r) => Ok(match boolean(l)? {
+                Some(false) => Val::Bool(true),
+                Some(true) => truth(boolean(r)?),
+                None => match boolean(r)? {
+                    Some(true) => Val::Bool(true),
+                    _ => Val::Unknown,
+                },
+            }),
+            Operation::Not(o) => Ok(truth(boolean(o)?.map(|b| !b))),
+            Operation::Gt(l, r) => compare(l, r, i64::gt),
+            Operation::Lt(l, r) => compare(l, r, i64::lt),
+            Operation::Leq(l, r) => compare(l, r, i64::le),
+            Operation::Geq(l, r) => compare(l, r, i64::ge),
+            Operation::Eq(l, r) => Ok(truth(self.equal(eval(l)?, eval(r)?)?)),
+            Operation::Neq(l, r) => Ok(truth(self.equal(eval(l)?, eval(r)?)?.map(|b| !b))),
+            Operation::Add(l, r) => arith(l, r, i64::wrapping_add),
+            Operation::Sub(l, r) => arith(l, r, i64::wrapping_sub),
+            Operation::Mul(l, r) => arith(l, r, i64::wrapping_mul),
+            Operation::Apply(_, _) => {
+                let Some((name, args)) = op.spine() else {
+                    return Err(format!("Expected a function name at the head of {op}"));
+                };
+                let args = args
+                    .into_iter()
+                    .map(eval)
+                    .collect::<Result<Vec<Val<'a, T>>, String>>()?;
+                self.call(name, &args, env, scope, depth)
+            }
+        }
+    }
+
+    // structural equality, None when it depends on a part that isn't known
+    fn equal<'a, T: Term>(&self, l: Val<'a, T>, r: Val<'a, T>) -> Result<Option<bool>, String>
+    where
+        'f: 'a,
+    {
+        Ok(match (l, r) {
+            (Val::Int(a), Val::Int(b)) => Some(a == b),
+            (Val::Bool(a), Val::Bool(b)) => Some(a == b),
+            (Val::Con(a, _), Val::Con(b, _)) if a != b => Some(false),
+            (Val::Con(_, fa), Val::Con(_, fb)) => {
+                let mut result = Some(true);
+                for (x, y) in fa.iter().zip(fb) {
+                    match self.equal(x.view(self.adt), y.view(self.adt))? {
+                        Some(false) => return Ok(Some(false)),
+                        Some(true) => {}
+                        None => result = None,
+                    }
+                }
+                result
+            }
+            (Val::Unknown, _) | (_, Val::Unknown) => None,
+            (l, r) => return Err(format!("Compared {l:?} with {r:?}")),
+        })
+    }
+}
+
+fn truth<'a, T>(b: Option<bool>) -> Val<'a, T> {
+    b.map_or(Val::Unknown, Val::Bool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parser::parse;
+
+    const COUNTDOWN: &str = "data Box = Box Int
+
+p :: Box -> Bool
+p (Box n) = go n
+  where
+    go 0 = True
+    go k = go (k - 1)
+";
+
+    #[test]
+    fn deep_recursion_is_cut_off_before_the_stack_runs_out() {
+        let (adt, funcs) = parse(COUNTDOWN, "hs", None, None, false);
+        let boxed = |n| Value::Con("Box".to_string(), vec![Value::Int(n)]);
+        let depth = i32::try_from(MAX_CALL_DEPTH).unwrap();
+        assert_eq!(evaluate(&adt, &funcs, &boxed(depth - 1)), Ok(true));
+        // counting down from a negative number never reaches the base case
+        assert_eq!(
+            evaluate(&adt, &funcs, &boxed(-1)),
+            Err("Call to go does not terminate".to_string())
+        );
+    }
+}
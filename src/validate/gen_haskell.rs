@@ -1,21 +1,19 @@
 // given the solutions from conjure and the parsed ADT and functions, write haskell code that validates the solutions
 use crate::adt::Adt;
 use crate::adt::Func;
+use crate::adt::Type;
+use crate::adt::Value;
+use crate::validate::parse_solution::decode_value;
 use serde;
 use serde::Deserialize;
 use serde::Serialize;
 use std::fmt::Write as _;
 use tera::{Context, Tera};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Assignments {
-    var: String,
-    val: String,
-}
-
 //hack to make func easier for the template, but probably could swap to just display later
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct FuncInput {
+    name: String,
     input: String,
     opp: String,
     where_clause: Option<String>,
@@ -25,15 +23,18 @@ struct FuncInput {
 pub fn generate_haskell_validation(
     adt: Adt,
     funcs: Vec<Func>,
-    assignments: &Vec<(String, String)>,
+    assignments: &[(String, String)],
+    expected: bool,
     verbose: bool,
 ) -> bool {
     let tera = Tera::new("src/templates/*.tera").unwrap();
     let mut context = Context::new();
 
     context.insert("adt", &adt);
+    context.insert("name", &funcs[0].name);
+    context.insert("types", &gen_signature(&adt));
     context.insert("funcs", &gen_predicate(funcs.clone()));
-    context.insert("assignments", &gen_value(adt, assignments, verbose));
+    context.insert("args", &gen_value(&adt, assignments, verbose));
 
     let validation_code = tera.render("haskell.tera", &context).unwrap();
 
@@ -74,8 +75,9 @@ pub fn generate_haskell_validation(
         );
     }
 
-    // check if the output contains "True"
-    String::from_utf8_lossy(&cmd.stdout).contains("True")
+    // check the program printed the expected result, True or False
+    let expected = if expected { "True" } else { "False" };
+    String::from_utf8_lossy(&cmd.stdout).trim() == expected
 }
 
 fn gen_predicate(funcs: Vec<Func>) -> Vec<FuncInput> {
@@ -102,7 +104,13 @@ fn gen_predicate(funcs: Vec<Func>) -> Vec<FuncInput> {
         }
 
         result.push(FuncInput {
-            input: func.con.to_string(),
+            name: func.name.clone(),
+            input: func
+                .patterns()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(" "),
             opp: pred_code,
             where_clause: if !func.local_binds.is_empty() {
                 Some(where_clause)
@@ -114,51 +122,28 @@ fn gen_predicate(funcs: Vec<Func>) -> Vec<FuncInput> {
     result
 }
 
-fn gen_value(adt: Adt, assignments: &Vec<(String, String)>, verbose: bool) -> Assignments {
-    // generate haskell code for values
-
-    // find the value of the tag in the assignments
-    let tag_value = assignments
+// the argument types of the predicate, the Adt being renamed to D
+fn gen_signature(adt: &Adt) -> String {
+    adt.args
         .iter()
-        .find(|(var, _)| var == "tag")
-        .map(|(_, val)| val.clone())
-        .unwrap();
-    let tag_value_int: i32 = tag_value.parse().unwrap();
+        .map(|t| match t {
+            Type::Int => "Int",
+            Type::Bool => "Bool",
+            Type::Custom(_) => "D",
+        })
+        .collect::<Vec<&str>>()
+        .join(" -> ")
+}
 
+fn gen_value(adt: &Adt, assignments: &[(String, String)], verbose: bool) -> String {
+    // generate haskell code for the arguments
+    let value = decode_value(adt, assignments);
     if verbose {
-        println!("Generating Haskell value code for tag value: {tag_value_int}");
-    }
-    /*  find the constructor corresponding to the tag value
-        // reverse the adt because of previous stack shenanigans
-        let adt = Adt {
-            name: adt.name,
-            constructors: adt.constructors.into_iter().rev().collect(),
-        };
-    */
-    let constructor = &adt.constructors[(tag_value_int - 1) as usize];
-    if verbose {
-        println!("Using constructor: {}", constructor.prefix);
-    }
-    let mut value = String::new();
-    // find the value of each field in the assignments where the variable name begins with the constructor prefix
-    for (var, val) in assignments {
-        if var.starts_with(&constructor.prefix) {
-            if verbose {
-                println!("Processing field assignment: {} = {}", var, val);
-            }
-            if val == "true" {
-                value.push_str("True ");
-                continue;
-            } else if val == "false" {
-                value.push_str("False ");
-                continue;
-            }
-            value.push_str(&format!("({val}) "));
-        }
+        println!("Generating Haskell value code for: {value}");
     }
 
-    Assignments {
-        var: constructor.prefix.clone(),
-        val: value.trim().to_string(),
+    match value {
+        Value::Args(_) => value.to_string(),
+        _ => value.to_haskell_arg(),
     }
 }
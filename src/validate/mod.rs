@@ -1,2 +1,3 @@
+pub mod evaluate;
 pub mod gen_haskell;
 pub mod parse_solution;
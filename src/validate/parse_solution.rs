@@ -1,3 +1,5 @@
+use crate::adt::{Adt, Type, Value, arg_variable};
+
 /// parses the solution output from Conjure
 #[must_use]
 pub fn parse_essence_output(sol_path: &str, verbose: bool) -> Vec<(String, String)> {
@@ -7,15 +9,29 @@ pub fn parse_essence_output(sol_path: &str, verbose: bool) -> Vec<(String, Strin
         println!("Solution:\n{}", &solution);
     }
 
-    // turn each line into a tuple of (variable, value) by splitting at the first word after "letting" and the word "be"
-    let assignments: Vec<(String, String)> = solution
-        .lines()
-        .filter(|line| line.starts_with("letting"))
-        .map(|line| {
-            let parts: Vec<&str> = line["letting ".len()..].split(" be ").collect();
-            (parts[0].trim().to_string(), parts[1].trim().to_string())
-        })
-        .collect();
+    // turn each line into a tuple of (variable, value) by splitting at the first word after "letting" and the word "be".
+    // structured values can run over several lines, which are joined back onto their letting
+    let mut lettings: Vec<(String, String)> = Vec::new();
+    for line in solution.lines() {
+        if let Some(letting) = line.strip_prefix("letting ") {
+            let (var, val) = letting
+                .trim_end()
+                .split_once(" be")
+                .unwrap_or_else(|| panic!("Expected letting <var> be <value>, found {line}"));
+            lettings.push((var.trim().to_string(), val.trim().to_string()));
+        } else if let Some((_, value)) = lettings.last_mut()
+            && !line.starts_with('$')
+            && !line.trim().is_empty()
+        {
+            value.push(' ');
+            value.push_str(line.trim());
+        }
+    }
+
+    let mut assignments = Vec::new();
+    for (var, val) in &lettings {
+        flatten(var, val, &mut assignments);
+    }
 
     for (var, val) in &assignments {
         if verbose {
@@ -25,3 +41,305 @@ pub fn parse_essence_output(sol_path: &str, verbose: bool) -> Vec<(String, Strin
 
     assignments
 }
+
+// splits a structured value into plain assignments: a variant assigns its active label to the
+// variable and a record assigns each field to its label, eg
+// `tag be variant {Rect = record {Rect_1 = 2, Rect_2 = 3}}` gives tag = Rect, Rect_1 = 2, Rect_2 = 3
+fn flatten(var: &str, value: &str, assignments: &mut Vec<(String, String)>) {
+    let value = value.trim();
+    let body = |keyword: &str| {
+        value
+            .strip_prefix(keyword)
+            .map(str::trim_start)
+            .and_then(|v| v.strip_prefix('{')?.strip_suffix('}'))
+    };
+    if let Some(body) = body("variant") {
+        let (label, inner) = body
+            .split_once('=')
+            .expect("Expected label = value in a variant");
+        assignments.push((var.to_string(), label.trim().to_string()));
+        flatten(label.trim(), inner, assignments);
+    } else if let Some(body) = body("record") {
+        // split at the commas between the fields, not those inside them
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, c) in body.char_indices().chain([(body.len(), ',')]) {
+            match c {
+                '{' | '(' | '[' => depth += 1,
+                '}' | ')' | ']' => depth -= 1,
+                ',' if depth == 0 => {
+                    let field = &body[start..i];
+                    let (label, inner) = field
+                        .split_once('=')
+                        .expect("Expected label = value in a record");
+                    flatten(label.trim(), inner, assignments);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+    } else {
+        assignments.push((var.to_string(), value.to_string()));
+    }
+}
+
+/// rebuilds the value of the Adt described by the assignments, or all the arguments of a
+/// predicate with several. pooled values are read back starting at the root slot
+/// # Panics
+/// Panics if the assignments are missing a tag or a field of the chosen constructor.
+#[must_use]
+pub fn decode_value(adt: &Adt, assignments: &[(String, String)]) -> Value {
+    if !adt.is_single_value() {
+        Value::Args(
+            adt.args
+                .iter()
+                .enumerate()
+                .map(|(i, t)| match t {
+                    Type::Custom(_) => decode_slot(adt, assignments, i, 1),
+                    Type::Bool => Value::Bool(lookup(assignments, &arg_variable(i)) == "true"),
+                    Type::Int => Value::Int(
+                        lookup(assignments, &arg_variable(i))
+                            .parse()
+                            .expect("Int argument is not an integer"),
+                    ),
+                })
+                .collect(),
+        )
+    } else if adt.is_recursive() {
+        decode_slot(adt, assignments, 0, 1)
+    } else {
+        decode_con(
+            adt,
+            assignments,
+            "tag",
+            |prefix, j| format!("{prefix}_{j}"),
+            || unreachable!("non-recursive Adts have no child values"),
+        )
+    }
+}
+
+fn decode_slot(adt: &Adt, assignments: &[(String, String)], arg: usize, slot: usize) -> Value {
+    let mut child = 0;
+    decode_con(
+        adt,
+        assignments,
+        &adt.slot_variable(arg, "tag", slot),
+        |prefix, j| adt.slot_variable(arg, &format!("{prefix}_{j}"), slot),
+        || {
+            child += 1;
+            decode_slot(adt, assignments, arg, adt.child_slot(slot, child - 1))
+        },
+    )
+}
+
+fn decode_con(
+    adt: &Adt,
+    assignments: &[(String, String)],
+    tag_var: &str,
+    field_var: impl Fn(&str, usize) -> String,
+    mut child: impl FnMut() -> Value,
+) -> Value {
+    let lookup = |var: &str| lookup(assignments, var);
+
+    // the tag is a number, or the constructor's name in the variant encoding
+    let tag = lookup(tag_var);
+    let constructor = match tag.parse::<usize>() {
+        Ok(tag) => adt.tagged(tag),
+        Err(_) => adt
+            .constructors
+            .iter()
+            .find(|c| c.prefix == tag)
+            .unwrap_or_else(|| panic!("tag {tag} is neither an index nor a constructor")),
+    };
+
+    let fields = constructor
+        .types
+        .iter()
+        .enumerate()
+        .map(|(j, t)| match t {
+            Type::Custom(_) => child(),
+            Type::Bool => Value::Bool(lookup(&field_var(&constructor.prefix, j + 1)) == "true"),
+            Type::Int => Value::Int(
+                lookup(&field_var(&constructor.prefix, j + 1))
+                    .parse()
+                    .expect("Int field is not an integer"),
+            ),
+        })
+        .collect();
+
+    Value::Con(constructor.prefix.clone(), fields)
+}
+
+fn lookup<'a>(assignments: &'a [(String, String)], var: &str) -> &'a str {
+    assignments
+        .iter()
+        .find(|(v, _)| v == var)
+        .map(|(_, val)| val.as_str())
+        .unwrap_or_else(|| panic!("No assignment for {var}"))
+}
+
+/// the inverse of `decode_value`, gives the assignments conjure would produce for the value.
+/// fields of the constructors not chosen are pinned to their defaults as in the Essence encoding
+#[must_use]
+pub fn encode_value(adt: &Adt, value: &Value, min: i32, depth: usize) -> Vec<(String, String)> {
+    let mut assignments = Vec::new();
+    match value {
+        Value::Args(args) => {
+            for (i, arg) in args.iter().enumerate() {
+                match arg {
+                    Value::Int(v) => assignments.push((arg_variable(i), v.to_string())),
+                    Value::Bool(b) => assignments.push((arg_variable(i), b.to_string())),
+                    _ => encode_slots(adt, arg, i, min, depth, &mut assignments),
+                }
+            }
+        }
+        _ if adt.is_recursive() => encode_slots(adt, value, 0, min, depth, &mut assignments),
+        _ => encode_con(adt, Some(value), "tag", min, &mut assignments, |p, j| {
+            format!("{p}_{j}")
+        }),
+    }
+    assignments
+}
+
+fn encode_slots(
+    adt: &Adt,
+    value: &Value,
+    arg: usize,
+    min: i32,
+    depth: usize,
+    assignments: &mut Vec<(String, String)>,
+) {
+    let mut slots = vec![None; adt.slot_count(depth)];
+    place_slot(adt, value, 1, &mut slots);
+    for (i, slot) in slots.iter().enumerate() {
+        encode_con(
+            adt,
+            *slot,
+            &adt.slot_variable(arg, "tag", i + 1),
+            min,
+            assignments,
+            |p, j| adt.slot_variable(arg, &format!("{p}_{j}"), i + 1),
+        );
+    }
+}
+
+// lays a recursive value out over the slot pool
+fn place_slot<'v>(adt: &Adt, value: &'v Value, slot: usize, slots: &mut [Option<&'v Value>]) {
+    slots[slot - 1] = Some(value);
+    if let Value::Con(_, fields) = value {
+        let children = fields.iter().filter(|f| matches!(f, Value::Con(_, _)));
+        for (child, field) in children.enumerate() {
+            place_slot(adt, field, adt.child_slot(slot, child), slots);
+        }
+    }
+}
+
+fn encode_con(
+    adt: &Adt,
+    value: Option<&Value>,
+    tag_var: &str,
+    min: i32,
+    assignments: &mut Vec<(String, String)>,
+    field_var: impl Fn(&str, usize) -> String,
+) {
+    // unused slots hold the first non-recursive constructor
+    let (prefix, fields) = match value {
+        Some(Value::Con(prefix, fields)) => (prefix.as_str(), &fields[..]),
+        Some(other) => panic!("Expected a constructor value, found {other}"),
+        None => {
+            let leaf = adt
+                .constructors
+                .iter()
+                .find(|c| c.recursive_fields() == 0)
+                .expect("Expected a non-recursive constructor");
+            (leaf.prefix.as_str(), &[][..])
+        }
+    };
+    assignments.push((tag_var.to_string(), adt.tag(prefix).to_string()));
+
+    for con in &adt.constructors {
+        for (j, t) in con.types.iter().enumerate() {
+            let val = match (t, fields.get(j)) {
+                (Type::Custom(_), _) => continue,
+                (_, Some(Value::Int(v))) if con.prefix == prefix => v.to_string(),
+                (_, Some(Value::Bool(b))) if con.prefix == prefix => b.to_string(),
+                (Type::Int, _) => min.to_string(),
+                (Type::Bool, _) => "false".to_string(),
+            };
+            assignments.push((field_var(&con.prefix, j + 1), val));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parser::parse;
+
+    fn flattened(var: &str, value: &str) -> Vec<(String, String)> {
+        let mut assignments = Vec::new();
+        flatten(var, value, &mut assignments);
+        assignments
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(var, val)| ((*var).to_string(), (*val).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn plain_values_are_kept() {
+        assert_eq!(flattened("tag", " 2 "), pairs(&[("tag", "2")]));
+    }
+
+    #[test]
+    fn variants_assign_their_label_and_records_their_fields() {
+        assert_eq!(
+            flattened("value", "variant {Rect = record {Rect_1 = 2, Rect_2 = -3}}"),
+            pairs(&[("value", "Rect"), ("Rect_1", "2"), ("Rect_2", "-3")])
+        );
+        // a constructor without fields holds a placeholder
+        assert_eq!(
+            flattened("value", "variant {Leaf = 0}"),
+            pairs(&[("value", "Leaf"), ("Leaf", "0")])
+        );
+    }
+
+    #[test]
+    fn records_split_only_at_their_own_commas() {
+        assert_eq!(
+            flattened("r", "record {a = record {b = 1, c = true}, d = false}"),
+            pairs(&[("b", "1"), ("c", "true"), ("d", "false")])
+        );
+    }
+
+    #[test]
+    fn rust_variant_fields_round_trip_in_declaration_order() {
+        let src = "enum Shape {
+    Dot,
+    Rect(i32, i32, bool),
+}
+
+fn fits(s: Shape) -> bool {
+    match s {
+        Shape::Dot => true,
+        Shape::Rect(w, h, b) => b && w < h,
+    }
+}
+";
+        let (adt, _) = parse(src, "rs", None, None, false);
+        assert_eq!(
+            adt.tagged(adt.tag("Rect")).types,
+            vec![Type::Int, Type::Int, Type::Bool]
+        );
+
+        let value = Value::Con(
+            "Rect".to_string(),
+            vec![Value::Int(2), Value::Int(-3), Value::Bool(true)],
+        );
+        let assignments = encode_value(&adt, &value, -5, 3);
+        assert_eq!(decode_value(&adt, &assignments), value);
+    }
+}